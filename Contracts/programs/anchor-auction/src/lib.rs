@@ -1,18 +1,80 @@
+// anchor_lang::Result carries the whole error, every handler returns it
+#![allow(clippy::result_large_err)]
+
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, CloseAccount, SetAuthority, TokenAccount, Transfer, Mint};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Approve, CloseAccount, MintTo, Revoke, SetAuthority, Token, TokenAccount, Transfer, Mint};
 use spl_token::instruction::AuthorityType;
 
 // Add these imports for Metaplex metadata verification
-use anchor_lang::solana_program::program::invoke;
-use anchor_lang::solana_program::program_pack::Pack;
-use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
 use std::str::FromStr;
 
 declare_id!("HGhUfApRyEBL758VLG5kq45UkEAsvaVcPvCxVHuXMdhU");
 
 // Define the Metaplex Token Metadata Program ID
 const METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+const ESCROW_PDA_SEED: &[u8] = b"escrow";
 const ROYALTY_CONFIG_SEED: &[u8] = b"royalty_config";
+const MAX_ROYALTY_BASIS_POINTS: u16 = 2500; // 25% max royalty
+const MAX_ROYALTY_RECIPIENTS: usize = 5;    // Maximum of 5 recipients
+const MUSIC_TRACK_SEED: &[u8] = b"music_track";
+const AUTH_REGISTRY_SEED: &[u8] = b"auth_registry";
+const USER_AUTH_SEED: &[u8] = b"user_auth";
+const USER_AUTH_CACHE_VERSION: u8 = 1;    // Layout of UserAuthCache, bumped when fields are added
+const MAX_TRACK_URI_LEN: usize = 200;     // Longest track, preview or metadata URI
+const MAX_AUTHORIZED_COLLECTIONS: usize = 20; // Collections or mints that can unlock a track
+const MAX_TOKEN_GATES: usize = 5;         // Fan token gates per track
+const MAX_PRERELEASE_COLLECTIONS: usize = 5; // Collections or mints let in before a release
+const BASE_ACCESS_TIER: u8 = 0;           // Tier granted unless a collection is assigned a higher one
+const MAX_ACCESS_TIER: u8 = 3;            // Highest tier, each tier above the base has its own URI
+const MAX_ACCESS_TIERS: usize = 4;        // Price/duration options for time passes on a track
+const ACCESS_PASS_SEED: &[u8] = b"access_pass";
+const SUBSCRIPTION_PLAN_SEED: &[u8] = b"subscription_plan";
+const SUBSCRIPTION_SEED: &[u8] = b"subscription";
+const PLAY_EPOCH_SEED: &[u8] = b"play_epoch";
+const PLAY_EPOCH_VAULT_SEED: &[u8] = b"play_epoch_vault";
+const TRACK_PLAYS_SEED: &[u8] = b"track_plays";
+const LISTENING_DELEGATION_SEED: &[u8] = b"listening_delegation";
+const SESSION_KEY_SEED: &[u8] = b"session_key";
+const MAX_SESSION_SCOPE: usize = 10;      // Tracks and artists a session key can be limited to
+const MAX_SESSION_DURATION: i64 = 7 * 24 * 3600; // Longest a session key stays valid
+const PLAY_EPOCH_SEC: i64 = 7 * 24 * 3600; // Length of a play counting and payout epoch
+const DEFAULT_AUTH_TTL: i64 = 3600;       // Seconds an authorization stays cached unless configured
+const MAX_AUTH_TTL: i64 = 7 * 24 * 3600;  // Longest configurable authorization lifetime
+const AUTH_CACHE_SWEEP_GRACE: i64 = 30 * 24 * 3600; // Idle time before anyone can close a cache
+const MAX_CANDLE_BIDS: usize = 10;        // Bids kept on-chain for a candle auction
+const CANDLE_SETTLE_DELAY_SLOTS: u64 = 32; // Gap between the last bid and the slot that picks the cutoff
+const EXPECTED_SLOT_MS: u64 = 400;        // Nominal slot time, used to commit to a slot past the end
+const EDITION_AUCTION_SEED: &[u8] = b"edition_auction";
+const EDITION_BID_SEED: &[u8] = b"edition_bid";
+const EDITION_VAULT_SEED: &[u8] = b"edition_vault";
+const EDITION_PAYMENT_VAULT_SEED: &[u8] = b"edition_payment_vault";
+const MAX_EDITION_PRICE_LEVELS: usize = 32; // Distinct bid prices per edition auction
+const LISTING_SEED: &[u8] = b"listing";
+const LISTING_VAULT_SEED: &[u8] = b"listing_vault";
+const OFFER_SEED: &[u8] = b"offer";
+const OFFER_VAULT_SEED: &[u8] = b"offer_vault";
+const COLLECTION_OFFER_SEED: &[u8] = b"collection_offer";
+const COLLECTION_OFFER_VAULT_SEED: &[u8] = b"collection_offer_vault";
+const RENTAL_SEED: &[u8] = b"rental";
+const RENTAL_VAULT_SEED: &[u8] = b"rental_vault";
+const SECONDS_PER_DAY: i64 = 24 * 3600;
+
+// Token Metadata account layout
+const METADATA_PREFIX: &[u8] = b"metadata";
+const METADATA_V1_KEY: u8 = 4;
+const METADATA_CREATOR_LEN: usize = 32 + 1 + 1; // address, verified, share
+
+// Token Metadata instructions that let a delegate freeze or thaw a token account through the master edition
+const FREEZE_DELEGATED_ACCOUNT_INSTRUCTION: u8 = 26;
+const THAW_DELEGATED_ACCOUNT_INSTRUCTION: u8 = 27;
 
 // Add error codes for NFT verification and royalty distribution
 #[error_code]
@@ -55,6 +117,122 @@ pub enum ErrorCode {
     MissingExpectedAccount,
     #[msg("Token mint mismatch")]
     TokenMintMismatch,
+    #[msg("Auction is not a candle auction")]
+    NotCandleAuction,
+    #[msg("Candle duration must be non-zero and within the auction duration")]
+    InvalidCandleDuration,
+    #[msg("Candle auction bid history is full, no more bids are accepted")]
+    CandleBidHistoryFull,
+    #[msg("Candle auction has not been settled")]
+    CandleAuctionNotSettled,
+    #[msg("Candle auction has already been settled")]
+    CandleAuctionAlreadySettled,
+    #[msg("Auction has not ended yet")]
+    AuctionNotEnded,
+    #[msg("Invalid candle bid")]
+    InvalidCandleBid,
+    #[msg("Losing candle bids must be refunded first")]
+    CandleRefundsPending,
+    #[msg("Invalid slot hashes sysvar")]
+    InvalidSlotHashes,
+    #[msg("Invalid edition auction parameters")]
+    InvalidEditionAuction,
+    #[msg("Edition auction has ended")]
+    EditionAuctionEnded,
+    #[msg("Edition auction has not been cleared")]
+    EditionAuctionNotCleared,
+    #[msg("Edition auction has already been cleared")]
+    EditionAuctionAlreadyCleared,
+    #[msg("Bid is below the reserve price")]
+    BidBelowReserve,
+    #[msg("Too many distinct bid prices in this edition auction")]
+    TooManyPriceLevels,
+    #[msg("Every edition bid must be claimed first")]
+    EditionClaimsPending,
+    #[msg("Invalid escrow PDA")]
+    InvalidEscrowPda,
+    #[msg("Listing does not match")]
+    InvalidListing,
+    #[msg("Offer does not match")]
+    InvalidOffer,
+    #[msg("Offer has expired")]
+    OfferExpired,
+    #[msg("NFT is not a verified member of the collection")]
+    NotInCollection,
+    #[msg("Collection offer is already filled")]
+    CollectionOfferFilled,
+    #[msg("Track URI is too long")]
+    TrackUriTooLong,
+    #[msg("Too many authorized collections on this track")]
+    TooManyCollections,
+    #[msg("Collection is not authorized for this track")]
+    CollectionNotAuthorized,
+    #[msg("Authorization is still valid")]
+    AuthorizationStillValid,
+    #[msg("Authorization TTL must be positive and at most 7 days")]
+    InvalidAuthTtl,
+    #[msg("Authorization cache is still within its grace period")]
+    AuthCacheNotSweepable,
+    #[msg("Too many token gates on this track")]
+    TooManyTokenGates,
+    #[msg("Token gate minimum amount must be non-zero")]
+    InvalidTokenGate,
+    #[msg("Access tiers need a non-zero price and duration")]
+    InvalidAccessTier,
+    #[msg("Too many access tiers on this track")]
+    TooManyAccessTiers,
+    #[msg("Access to this track is not for sale")]
+    AccessNotForSale,
+    #[msg("Subscription plan needs a non-zero price and period")]
+    InvalidSubscriptionPlan,
+    #[msg("Subscription does not match")]
+    InvalidSubscription,
+    #[msg("Play epoch is not the current one")]
+    InvalidPlayEpoch,
+    #[msg("Play epoch has not ended")]
+    PlayEpochNotEnded,
+    #[msg("Play epoch has ended")]
+    PlayEpochEnded,
+    #[msg("Signer is not the play attestor")]
+    UnauthorizedAttestor,
+    #[msg("Plays already claimed for this epoch")]
+    PlaysAlreadyClaimed,
+    #[msg("Listening delegation does not match")]
+    InvalidDelegation,
+    #[msg("Listening delegation has expired")]
+    DelegationExpired,
+    #[msg("Rental does not match")]
+    InvalidRental,
+    #[msg("NFT is currently rented")]
+    NftAlreadyRented,
+    #[msg("Rental term has not ended")]
+    RentalNotEnded,
+    #[msg("Holders-only window must end after the release")]
+    InvalidReleaseWindow,
+    #[msg("Track has not been released")]
+    TrackNotReleased,
+    #[msg("Invalid collection tier")]
+    InvalidTier,
+    #[msg("Signer is neither the user nor one of their session keys")]
+    InvalidSessionKey,
+    #[msg("Session key has expired")]
+    SessionKeyExpired,
+    #[msg("Track is outside the session key's scope")]
+    SessionKeyOutOfScope,
+    #[msg("Candle settlement slot has not been reached")]
+    CandleSlotNotReached,
+    #[msg("No plays were counted for this track in the epoch")]
+    NoPlaysCounted,
+    #[msg("Access pass does not match")]
+    InvalidAccessPass,
+    #[msg("Authorization cache does not match")]
+    InvalidAuthCache,
+    #[msg("Collection offer still has items to fill")]
+    CollectionOfferNotFilled,
+    #[msg("Authorization cache has an outdated layout, close and recreate it")]
+    OutdatedAuthCache,
+    #[msg("Authorization registry is already in the current layout")]
+    RegistryAlreadyMigrated,
 }
 
 #[program]
@@ -62,14 +240,6 @@ pub mod anchor_auction {
     use std::ops::Add;
     use super::*;
 
-    const ESCROW_PDA_SEED: &[u8] = b"escrow";
-    const MUSIC_TRACK_SEED: &[u8] = b"music_track";
-    const AUTH_REGISTRY_SEED: &[u8] = b"auth_registry";
-    const USER_AUTH_SEED: &[u8] = b"user_auth";
-    
-    const MAX_ROYALTY_BASIS_POINTS: u16 = 2500; // 25% max royalty
-    const MAX_ROYALTY_RECIPIENTS: usize = 5;    // Maximum of 5 recipients
-
     pub fn exhibit(
         ctx: Context<Exhibit>,
         initial_price: u64,
@@ -83,6 +253,11 @@ pub mod anchor_auction {
         ctx.accounts.escrow_account.highest_bidder_ft_returning_pubkey = ctx.accounts.exhibitor_ft_receiving_account.key();
         ctx.accounts.escrow_account.price = initial_price;
         ctx.accounts.escrow_account.end_at = ctx.accounts.clock.unix_timestamp.add(auction_duration_sec as i64);
+        // Optionally link a track so the winner gets playback access on close, only its authority can
+        if let Some(music_track) = &ctx.accounts.music_track {
+            require!(music_track.authority == ctx.accounts.exhibitor.key(), ErrorCode::UnauthorizedPlayback);
+            ctx.accounts.escrow_account.music_track = music_track.key();
+        }
 
         let (pda, _bump_seed) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
         token::set_authority(
@@ -99,9 +274,60 @@ pub mod anchor_auction {
         Ok(())
     }
 
+    // List an NFT without moving it: the escrow PDA becomes the delegate of the
+    // seller's token account and freezes it in place until the auction is closed or cancelled
+    pub fn exhibit_in_place(
+        ctx: Context<ExhibitInPlace>,
+        initial_price: u64,
+        auction_duration_sec: u64,
+    ) -> Result<()> {
+        let (pda, bump_seed) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
+        let signers_seeds: &[&[&[u8]]] = &[&[ESCROW_PDA_SEED, &[bump_seed]]];
+        require!(ctx.accounts.pda.key() == pda, ErrorCode::InvalidEscrowPda);
+
+        ctx.accounts.escrow_account.exhibitor_pubkey = ctx.accounts.exhibitor.key();
+        ctx.accounts.escrow_account.exhibitor_ft_receiving_pubkey = ctx.accounts.exhibitor_ft_receiving_account.key();
+        ctx.accounts.escrow_account.exhibiting_nft_temp_pubkey = ctx.accounts.exhibitor_nft_token_account.key();
+        ctx.accounts.escrow_account.highest_bidder_pubkey = ctx.accounts.exhibitor.key();
+        ctx.accounts.escrow_account.highest_bidder_ft_temp_pubkey = ctx.accounts.exhibitor_ft_receiving_account.key();
+        ctx.accounts.escrow_account.highest_bidder_ft_returning_pubkey = ctx.accounts.exhibitor_ft_receiving_account.key();
+        ctx.accounts.escrow_account.price = initial_price;
+        ctx.accounts.escrow_account.end_at = ctx.accounts.clock.unix_timestamp.add(auction_duration_sec as i64);
+        ctx.accounts.escrow_account.is_in_place = true;
+        if let Some(music_track) = &ctx.accounts.music_track {
+            require!(music_track.authority == ctx.accounts.exhibitor.key(), ErrorCode::UnauthorizedPlayback);
+            ctx.accounts.escrow_account.music_track = music_track.key();
+        }
+
+        token::approve(
+            ctx.accounts.to_approve_context(),
+            1,
+        )?;
+
+        toggle_delegated_freeze(
+            FREEZE_DELEGATED_ACCOUNT_INSTRUCTION,
+            &ctx.accounts.metadata_program,
+            &ctx.accounts.pda,
+            &ctx.accounts.exhibitor_nft_token_account.to_account_info(),
+            &ctx.accounts.nft_edition,
+            &ctx.accounts.nft_mint.to_account_info(),
+            &ctx.accounts.token_program,
+            signers_seeds,
+        )?;
+
+        Ok(())
+    }
+
     pub fn cancel(ctx: Context<Cancel> ) -> Result<()> {
         let (_, bump_seed) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
-        let signers_seeds: &[&[&[u8]]] = &[&[&ESCROW_PDA_SEED[..], &[bump_seed]]];
+        let signers_seeds: &[&[&[u8]]] = &[&[ESCROW_PDA_SEED, &[bump_seed]]];
+
+        if ctx.accounts.escrow_account.is_in_place {
+            // The NFT never left the seller, just unfreeze it and drop the delegation
+            ctx.accounts.thaw_in_place_nft(signers_seeds)?;
+            token::revoke(ctx.accounts.to_revoke_context())?;
+            return Ok(());
+        }
 
         token::transfer(
             ctx.accounts
@@ -119,11 +345,48 @@ pub mod anchor_auction {
         Ok(())
     }
 
+    // Start a candle auction: bids placed during the last `candle_duration_sec` only count
+    // if they land before a cutoff that is picked retroactively by `settle_candle`.
+    // The cutoff comes from the hash of a slot committed to here, expected shortly after the end.
+    pub fn exhibit_candle(
+        ctx: Context<Exhibit>,
+        initial_price: u64,
+        auction_duration_sec: u64,
+        candle_duration_sec: u64,
+    ) -> Result<()> {
+        require!(
+            candle_duration_sec > 0 && candle_duration_sec <= auction_duration_sec,
+            ErrorCode::InvalidCandleDuration
+        );
+
+        let end_at = ctx.accounts.clock.unix_timestamp.add(auction_duration_sec as i64);
+        let expected_slots = auction_duration_sec.saturating_mul(1000) / EXPECTED_SLOT_MS;
+        ctx.accounts.escrow_account.is_candle = true;
+        ctx.accounts.escrow_account.candle_start_at = end_at - candle_duration_sec as i64;
+        ctx.accounts.escrow_account.candle_settle_slot = ctx.accounts.clock.slot
+            + expected_slots
+            + CANDLE_SETTLE_DELAY_SLOTS;
+
+        exhibit(ctx, initial_price, auction_duration_sec)
+    }
+
+    // During the candle phase every bid is kept so the cutoff can pick any of them, once
+    // MAX_CANDLE_BIDS are recorded the auction takes no more bids
     pub fn bid(ctx: Context<Bid>, price: u64) -> Result<()> {
         let (pda, bump_seed) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
-        let signers_seeds: &[&[&[u8]]] = &[&[&ESCROW_PDA_SEED[..], &[bump_seed]]];
+        let signers_seeds: &[&[&[u8]]] = &[&[ESCROW_PDA_SEED, &[bump_seed]]];
 
-        if ctx.accounts.escrow_account.highest_bidder_pubkey != ctx.accounts.escrow_account.exhibitor_pubkey {
+        // Once the candle is lit outbid funds stay in escrow until the cutoff is known
+        let now = ctx.accounts.clock.unix_timestamp;
+        let in_candle_phase = ctx.accounts.escrow_account.is_candle
+            && now >= ctx.accounts.escrow_account.candle_start_at;
+
+        require!(
+            !in_candle_phase || (ctx.accounts.escrow_account.candle_bid_count as usize) < MAX_CANDLE_BIDS,
+            ErrorCode::CandleBidHistoryFull
+        );
+
+        if !in_candle_phase && ctx.accounts.escrow_account.highest_bidder_pubkey != ctx.accounts.escrow_account.exhibitor_pubkey {
             token::transfer(
                 ctx.accounts
                     .to_transfer_to_previous_bidder_context()
@@ -148,6 +411,30 @@ pub mod anchor_auction {
             price,
         )?;
 
+        if ctx.accounts.escrow_account.is_candle {
+            let escrow_account = &mut ctx.accounts.escrow_account;
+            if !in_candle_phase {
+                // The previous bid was refunded above, so only this one can still win
+                escrow_account.candle_bid_count = 0;
+            }
+            let index = escrow_account.candle_bid_count as usize;
+            escrow_account.candle_bids[index] = CandleBid {
+                bidder_pubkey: ctx.accounts.bidder.key(),
+                bidder_ft_temp_pubkey: ctx.accounts.bidder_ft_temp_account.key(),
+                bidder_ft_returning_pubkey: ctx.accounts.bidder_ft_account.key(),
+                price,
+                placed_at: now,
+                refunded: false,
+            };
+            escrow_account.candle_bid_count += 1;
+
+            // Every bid must land well before the slot whose hash picks the cutoff
+            let min_settle_slot = ctx.accounts.clock.slot + CANDLE_SETTLE_DELAY_SLOTS;
+            if escrow_account.candle_settle_slot < min_settle_slot {
+                escrow_account.candle_settle_slot = min_settle_slot;
+            }
+        }
+
         ctx.accounts.escrow_account.price = price;
         ctx.accounts.escrow_account.highest_bidder_pubkey = ctx.accounts.bidder.key();
         ctx.accounts.escrow_account.highest_bidder_ft_temp_pubkey = ctx.accounts.bidder_ft_temp_account.key();
@@ -156,37 +443,420 @@ pub mod anchor_auction {
         Ok(())
     }
 
-    pub fn close(ctx: Context<Close>) -> Result<()> {
+    // Pick the hidden cutoff of a candle auction and make the last bid before it the winner.
+    // The cutoff is derived from the hash of the committed slot, which comes after every bid,
+    // so neither bidders nor whoever settles can know or choose it in advance.
+    // If nobody settles while that hash is in the SlotHashes sysvar, the cutoff falls back to
+    // the end of the auction, so a late settlement can't re-roll it. Anyone can call this
+    pub fn settle_candle(ctx: Context<SettleCandle>) -> Result<()> {
+        let escrow_key = ctx.accounts.escrow_account.key();
+        let settle_slot_hash = find_slot_hash(&ctx.accounts.slot_hashes, ctx.accounts.escrow_account.candle_settle_slot)?;
+        let escrow_account = &mut ctx.accounts.escrow_account;
+
+        let cutoff = match settle_slot_hash {
+            SlotHashLookup::Found(hash) => {
+                let entropy = keccak::hashv(&[&hash, escrow_key.as_ref()]).to_bytes();
+                let mut entropy_bytes = [0u8; 8];
+                entropy_bytes.copy_from_slice(&entropy[..8]);
+                let window = (escrow_account.end_at - escrow_account.candle_start_at) as u64;
+                escrow_account.candle_start_at + (u64::from_le_bytes(entropy_bytes) % (window + 1)) as i64
+            }
+            SlotHashLookup::NotReached => return Err(ErrorCode::CandleSlotNotReached.into()),
+            SlotHashLookup::Expired => escrow_account.end_at,
+        };
+
+        let winner = escrow_account.candle_bids[..escrow_account.candle_bid_count as usize]
+            .iter()
+            .rev()
+            .find(|candle_bid| candle_bid.placed_at <= cutoff)
+            .copied();
+
+        match winner {
+            Some(candle_bid) => {
+                escrow_account.price = candle_bid.price;
+                escrow_account.highest_bidder_pubkey = candle_bid.bidder_pubkey;
+                escrow_account.highest_bidder_ft_temp_pubkey = candle_bid.bidder_ft_temp_pubkey;
+                escrow_account.highest_bidder_ft_returning_pubkey = candle_bid.bidder_ft_returning_pubkey;
+            }
+            None => {
+                // Nobody bid before the cutoff, hand the auction back to the exhibitor so it can be cancelled
+                escrow_account.highest_bidder_pubkey = escrow_account.exhibitor_pubkey;
+                escrow_account.highest_bidder_ft_temp_pubkey = escrow_account.exhibitor_ft_receiving_pubkey;
+                escrow_account.highest_bidder_ft_returning_pubkey = escrow_account.exhibitor_ft_receiving_pubkey;
+            }
+        }
+
+        escrow_account.candle_end_at = cutoff;
+        escrow_account.candle_settled = true;
+        msg!("Candle auction settled with cutoff {}", cutoff);
+
+        Ok(())
+    }
+
+    // Return the escrowed funds of a candle bid that lost, anyone can trigger this
+    pub fn refund_candle_bid(ctx: Context<RefundCandleBid>, bid_index: u8) -> Result<()> {
         let (_, bump_seed) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
-        let signers_seeds: &[&[&[u8]]] = &[&[&ESCROW_PDA_SEED[..], &[bump_seed]]];
+        let signers_seeds: &[&[&[u8]]] = &[&[ESCROW_PDA_SEED, &[bump_seed]]];
+
+        let candle_bid = ctx.accounts.escrow_account.candle_bids[bid_index as usize];
+        require!(!candle_bid.refunded, ErrorCode::InvalidCandleBid);
+        require!(
+            candle_bid.bidder_ft_temp_pubkey != ctx.accounts.escrow_account.highest_bidder_ft_temp_pubkey,
+            ErrorCode::InvalidCandleBid
+        );
+        require!(
+            candle_bid.bidder_pubkey == ctx.accounts.bidder.key()
+                && candle_bid.bidder_ft_temp_pubkey == ctx.accounts.bidder_ft_temp_account.key()
+                && candle_bid.bidder_ft_returning_pubkey == ctx.accounts.bidder_ft_returning_account.key(),
+            ErrorCode::InvalidCandleBid
+        );
 
         token::transfer(
             ctx.accounts
-                .to_transfer_to_highest_bidder_context()
+                .to_transfer_to_bidder_context()
                 .with_signer(signers_seeds),
-            ctx.accounts.exhibitor_nft_temp_account.amount,
+            ctx.accounts.bidder_ft_temp_account.amount,
         )?;
 
-        token::transfer(
+        token::close_account(
             ctx.accounts
-                .to_transfer_to_exhibitor_context()
+                .to_close_context()
                 .with_signer(signers_seeds),
-            ctx.accounts.highest_bidder_ft_temp_account.amount,
         )?;
 
-        token::close_account(
-            ctx.accounts.to_close_ft_context()
-                .with_signer(signers_seeds),
+        ctx.accounts.escrow_account.candle_bids[bid_index as usize].refunded = true;
+
+        Ok(())
+    }
+
+    pub fn close(ctx: Context<Close>) -> Result<()> {
+        // Auctions linked to a track go through close_with_track so the winner gets access
+        require!(
+            ctx.accounts.escrow_account.music_track == Pubkey::default(),
+            ErrorCode::InvalidMusicTrack
+        );
+        ctx.accounts.settle(ctx.program_id)
+    }
+
+    // Close an auction linked to a track and give the winner playback access. The NFT's verified
+    // collection is authorized on the track, usually it already is, or the NFT's own mint when it
+    // has none. With the collection list full this fails with TooManyCollections until the track
+    // authority makes room, since an unrecorded grant would outlive the winner selling the NFT.
+    pub fn close_with_track(ctx: Context<CloseWithTrack>) -> Result<()> {
+        let nft_mint = ctx.accounts.close.exhibitor_nft_temp_account.mint;
+        ctx.accounts.close.settle(ctx.program_id)?;
+
+        let music_track = &mut ctx.accounts.music_track;
+        let granted_via = verified_collection(&ctx.accounts.nft_metadata, &nft_mint)?.unwrap_or(nft_mint);
+        music_track.authorize_collection(granted_via)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let winner_auth = &mut ctx.accounts.winner_auth;
+        require!(
+            winner_auth.payer == Pubkey::default() || winner_auth.version == USER_AUTH_CACHE_VERSION,
+            ErrorCode::OutdatedAuthCache
+        );
+        winner_auth.version = USER_AUTH_CACHE_VERSION;
+        winner_auth.user = ctx.accounts.close.winning_bidder.key();
+        winner_auth.track_id = music_track.track_id.clone();
+        winner_auth.expires_at = now + music_track.auth_ttl;
+        winner_auth.granted_via = granted_via;
+        winner_auth.tier = music_track.tier_for(&granted_via);
+        if winner_auth.payer == Pubkey::default() {
+            winner_auth.payer = ctx.accounts.close.winning_bidder.key();
+            winner_auth.created_at = now;
+        }
+
+        Ok(())
+    }
+
+    // Start a uniform-price auction for `edition_count` units of an edition mint, minted on claim.
+    // The auction PDA must be the mint authority, it's handed back to the seller on withdrawal.
+    // `auction_id` lets a seller auction the same mint again.
+    pub fn create_edition_auction(
+        ctx: Context<CreateEditionAuction>,
+        auction_id: u64,
+        edition_count: u32,
+        reserve_price: u64,
+        auction_duration_sec: u64,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.edition_mint.mint_authority == COption::Some(ctx.accounts.edition_auction.key()),
+            ErrorCode::InvalidEditionAuction
+        );
+
+        let bump = *ctx.bumps.get("edition_auction").unwrap();
+        ctx.accounts.init_edition_auction(auction_id, edition_count, reserve_price, auction_duration_sec, true, bump)
+    }
+
+    // Same as create_edition_auction, but the seller escrows the editions up front
+    pub fn create_escrowed_edition_auction(
+        ctx: Context<CreateEscrowedEditionAuction>,
+        auction_id: u64,
+        edition_count: u32,
+        reserve_price: u64,
+        auction_duration_sec: u64,
+    ) -> Result<()> {
+        let bump = *ctx.bumps.get("edition_auction").unwrap();
+        ctx.accounts.create.init_edition_auction(auction_id, edition_count, reserve_price, auction_duration_sec, false, bump)?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.create.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.seller_edition_account.to_account_info(),
+                    to: ctx.accounts.create.edition_vault.to_account_info(),
+                    authority: ctx.accounts.create.seller.to_account_info(),
+                },
+            ),
+            edition_count as u64,
         )?;
 
-        token::close_account(
-            ctx.accounts.to_close_nft_context()
-                .with_signer(signers_seeds),
+        Ok(())
+    }
+
+    // Bid for `quantity` editions at `price` per edition, the full amount is escrowed.
+    // A bidder numbers their bids with `bid_index` to bid at more than one price
+    pub fn place_edition_bid(
+        ctx: Context<PlaceEditionBid>,
+        bid_index: u16,
+        quantity: u32,
+        price: u64,
+    ) -> Result<()> {
+        let edition_auction = &mut ctx.accounts.edition_auction;
+        require!(
+            quantity > 0 && quantity <= edition_auction.edition_count,
+            ErrorCode::InvalidEditionAuction
+        );
+        require!(price >= edition_auction.reserve_price && price > 0, ErrorCode::BidBelowReserve);
+
+        let deposit = (quantity as u64)
+            .checked_mul(price)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Keep price levels sorted from highest to lowest so clearing is a single pass
+        let level_offset = match edition_auction.price_levels.iter().position(|level| level.price <= price) {
+            Some(index) if edition_auction.price_levels[index].price == price => {
+                let level = &mut edition_auction.price_levels[index];
+                let offset = level.quantity;
+                level.quantity = level.quantity
+                    .checked_add(quantity as u64)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                offset
+            }
+            position => {
+                require!(
+                    edition_auction.price_levels.len() < MAX_EDITION_PRICE_LEVELS,
+                    ErrorCode::TooManyPriceLevels
+                );
+                let index = position.unwrap_or(edition_auction.price_levels.len());
+                edition_auction.price_levels.insert(index, EditionPriceLevel {
+                    price,
+                    quantity: quantity as u64,
+                });
+                0
+            }
+        };
+        edition_auction.bid_count = edition_auction.bid_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let edition_bid = &mut ctx.accounts.edition_bid;
+        edition_bid.auction = edition_auction.key();
+        edition_bid.bidder = ctx.accounts.bidder.key();
+        edition_bid.quantity = quantity;
+        edition_bid.price = price;
+        edition_bid.level_offset = level_offset;
+        edition_bid.bid_index = bid_index;
+        edition_bid.bump = *ctx.bumps.get("edition_bid").unwrap();
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.bidder_payment_account.to_account_info(),
+                    to: ctx.accounts.payment_vault.to_account_info(),
+                    authority: ctx.accounts.bidder.to_account_info(),
+                },
+            ),
+            deposit,
         )?;
 
         Ok(())
     }
 
+    // Compute the clearing price once bidding is over, anyone can call this
+    pub fn clear_edition_auction(ctx: Context<ClearEditionAuction>) -> Result<()> {
+        let edition_auction = &mut ctx.accounts.edition_auction;
+        let supply = edition_auction.edition_count as u64;
+
+        let mut units_sold: u64 = 0;
+        let mut clearing_price: u64 = 0;
+        let mut marginal_units: u64 = 0;
+        for level in edition_auction.price_levels.iter() {
+            clearing_price = level.price;
+            marginal_units = std::cmp::min(level.quantity, supply - units_sold);
+            units_sold += marginal_units;
+            if units_sold == supply {
+                break;
+            }
+        }
+
+        edition_auction.clearing_price = clearing_price;
+        edition_auction.units_sold = units_sold as u32;
+        edition_auction.marginal_units = marginal_units;
+        edition_auction.is_cleared = true;
+        msg!("Edition auction cleared at {} for {} units", clearing_price, units_sold);
+
+        Ok(())
+    }
+
+    // Deliver won editions at the clearing price and refund the rest of the deposit.
+    // Anyone can call this so the seller isn't kept waiting on bidders, `payer` funds the edition account
+    pub fn claim_edition_bid(ctx: Context<ClaimEditionBid>) -> Result<()> {
+        let edition_auction = &ctx.accounts.edition_auction;
+        let edition_bid = &ctx.accounts.edition_bid;
+
+        let filled = edition_auction.filled_units(edition_bid);
+        let deposit = (edition_bid.quantity as u64)
+            .checked_mul(edition_bid.price)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let cost = filled
+            .checked_mul(edition_auction.clearing_price)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let refund = deposit.checked_sub(cost).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let auction_id = edition_auction.auction_id.to_le_bytes();
+        let seeds = &[
+            EDITION_AUCTION_SEED,
+            edition_auction.seller.as_ref(),
+            edition_auction.edition_mint.as_ref(),
+            &auction_id,
+            &[edition_auction.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        if filled > 0 {
+            if edition_auction.is_minted {
+                token::mint_to(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        MintTo {
+                            mint: ctx.accounts.edition_mint.to_account_info(),
+                            to: ctx.accounts.bidder_edition_account.to_account_info(),
+                            authority: edition_auction.to_account_info(),
+                        },
+                        signer,
+                    ),
+                    filled,
+                )?;
+            } else {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: ctx.accounts.edition_vault.to_account_info(),
+                            to: ctx.accounts.bidder_edition_account.to_account_info(),
+                            authority: edition_auction.to_account_info(),
+                        },
+                        signer,
+                    ),
+                    filled,
+                )?;
+            }
+        }
+
+        if refund > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.payment_vault.to_account_info(),
+                        to: ctx.accounts.bidder_payment_account.to_account_info(),
+                        authority: edition_auction.to_account_info(),
+                    },
+                    signer,
+                ),
+                refund,
+            )?;
+        }
+
+        let edition_auction = &mut ctx.accounts.edition_auction;
+        edition_auction.claimed_count = edition_auction.claimed_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    // Pay the seller for the editions sold, return unsold escrowed editions and close the auction.
+    // Every bid has to be claimed first since claims pay out of the auction's vaults
+    pub fn withdraw_edition_proceeds(ctx: Context<WithdrawEditionProceeds>) -> Result<()> {
+        let edition_auction = &ctx.accounts.edition_auction;
+
+        let auction_id = edition_auction.auction_id.to_le_bytes();
+        let seeds = &[
+            EDITION_AUCTION_SEED,
+            edition_auction.seller.as_ref(),
+            edition_auction.edition_mint.as_ref(),
+            &auction_id,
+            &[edition_auction.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // With every bid claimed, what's left in the vaults is the seller's: the proceeds, unsold
+        // escrowed editions and anything sent to the vaults directly. Moving the live balances
+        // keeps a stray deposit from blocking the close below
+        let vaults = [
+            (&ctx.accounts.payment_vault, ctx.accounts.seller_payment_account.to_account_info()),
+            (&ctx.accounts.edition_vault, ctx.accounts.seller_edition_account.to_account_info()),
+        ];
+        for (vault, destination) in vaults {
+            if vault.amount > 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: vault.to_account_info(),
+                            to: destination,
+                            authority: edition_auction.to_account_info(),
+                        },
+                        signer,
+                    ),
+                    vault.amount,
+                )?;
+            }
+
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault.to_account_info(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: edition_auction.to_account_info(),
+                },
+                signer,
+            ))?;
+        }
+
+        // The seller gets the mint back so the auction PDA can't mint past the auction
+        if edition_auction.is_minted {
+            token::set_authority(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    SetAuthority {
+                        current_authority: edition_auction.to_account_info(),
+                        account_or_mint: ctx.accounts.edition_mint.to_account_info(),
+                    },
+                    signer,
+                ),
+                AuthorityType::MintTokens,
+                Some(edition_auction.seller),
+            )?;
+        }
+
+        Ok(())
+    }
+
     // Add new function for registering a music track
     pub fn register_music_track(
         ctx: Context<RegisterMusicTrack>,
@@ -196,6 +866,10 @@ pub mod anchor_auction {
         metadata_uri: String,
         is_public: bool,
     ) -> Result<()> {
+        for uri in [&track_uri, &preview_uri, &metadata_uri] {
+            require!(uri.len() <= MAX_TRACK_URI_LEN, ErrorCode::TrackUriTooLong);
+        }
+        
         let music_track = &mut ctx.accounts.music_track;
         
         // Initialize the music track account
@@ -206,159 +880,712 @@ pub mod anchor_auction {
         music_track.metadata_uri = metadata_uri;
         music_track.is_public = is_public;
         music_track.authorized_collections = Vec::new();
+        music_track.token_gates = Vec::new();
+        music_track.access_payment_mint = Pubkey::default();
+        music_track.royalty_mint = Pubkey::default();
+        music_track.access_tiers = Vec::new();
+        music_track.release_at = 0;
+        music_track.holders_only_until = 0;
+        music_track.prerelease_collections = Vec::new();
+        music_track.collection_tiers = Vec::new();
+        music_track.tier_uris = Vec::new();
         
         // Update the registry
         let registry = &mut ctx.accounts.auth_registry;
-        if registry.track_count == 0 {
+        if registry.authority == Pubkey::default() {
             // First-time initialization of registry
             registry.authority = ctx.accounts.authority.key();
+            registry.default_auth_ttl = DEFAULT_AUTH_TTL;
         }
-        registry.track_count = registry.track_count.checked_add(1).unwrap();
+        registry.track_count = registry.track_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        music_track.auth_ttl = registry.default_auth_ttl;
         
         Ok(())
     }
     
-    // Add function to add authorized collection to a track
-    pub fn add_authorized_collection(
-        ctx: Context<UpdateTrackAuthorization>,
-        collection_mint: Pubkey,
+    // Move a track registered under the old, unscoped seeds to its authority's namespace
+    pub fn migrate_music_track(
+        ctx: Context<MigrateMusicTrack>,
+        _track_id: String,
     ) -> Result<()> {
+        // Only the original fields carry over, everything added since starts at its default
+        // and the authorization TTL at the registry's default for new tracks
         let music_track = &mut ctx.accounts.music_track;
+        **music_track = ctx.accounts.legacy_music_track.to_music_track();
+        music_track.auth_ttl = ctx.accounts.auth_registry.default_auth_ttl;
         
-        // Ensure only the track authority can add collections
+        Ok(())
+    }
+    
+    // Grow a registry created before the TTL and attestor settings to the current layout
+    pub fn migrate_registry(ctx: Context<MigrateRegistry>) -> Result<()> {
+        let registry_info = ctx.accounts.auth_registry.to_account_info();
+        let new_size = get_auth_registry_size();
+        require!(registry_info.data_len() < new_size, ErrorCode::RegistryAlreadyMigrated);
+        
+        let legacy_registry: LegacyAuthorizationRegistry = deserialize_legacy_account(
+            &mut &registry_info.try_borrow_data()?[..],
+            AuthorizationRegistry::discriminator(),
+        )?;
         require!(
-            music_track.authority == ctx.accounts.authority.key(),
+            legacy_registry.authority == ctx.accounts.authority.key(),
             ErrorCode::UnauthorizedPlayback
         );
         
-        // Add the collection to authorized list if not already present
-        if !music_track.authorized_collections.contains(&collection_mint) {
-            // Limit the number of collections to prevent excessive storage costs
-            require!(
-                music_track.authorized_collections.len() < 20,
-                ErrorCode::InvalidMusicTrack
-            );
-            
-            music_track.authorized_collections.push(collection_mint);
-        }
+        grow_account(
+            &registry_info,
+            new_size,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+        
+        let registry = AuthorizationRegistry {
+            authority: legacy_registry.authority,
+            track_count: legacy_registry.track_count,
+            default_auth_ttl: DEFAULT_AUTH_TTL,
+            play_attestor: Pubkey::default(),
+            bump: legacy_registry.bump,
+        };
+        registry.try_serialize(&mut &mut registry_info.try_borrow_mut_data()?[..])?;
         
         Ok(())
     }
     
-    // Add function to verify playback authorization
-    pub fn verify_playback_authorization(
-        ctx: Context<VerifyPlaybackAuthorization>,
+    // Update the URIs or visibility of a music track, growing the account if needed
+    pub fn update_music_track(
+        ctx: Context<UpdateMusicTrack>,
+        track_uri: Option<String>,
+        preview_uri: Option<String>,
+        metadata_uri: Option<String>,
+        is_public: Option<bool>,
     ) -> Result<()> {
-        let music_track = &ctx.accounts.music_track;
-        let user = &ctx.accounts.user;
-        
-        // Check if there's a valid cached authorization
-        if let Some(user_auth) = &ctx.accounts.user_auth {
-            // Verify cache hasn't expired
-            if user_auth.expires_at > Clock::get()?.unix_timestamp {
-                return Ok(());
-            } else {
-                // Authorization expired - continue with verification
-            }
+        for uri in [&track_uri, &preview_uri, &metadata_uri].into_iter().flatten() {
+            require!(uri.len() <= MAX_TRACK_URI_LEN, ErrorCode::TrackUriTooLong);
+        }
+
+        let music_track = &mut ctx.accounts.music_track;
+        if let Some(track_uri) = track_uri {
+            music_track.track_uri = track_uri;
+        }
+        if let Some(preview_uri) = preview_uri {
+            music_track.preview_uri = preview_uri;
+        }
+        if let Some(metadata_uri) = metadata_uri {
+            music_track.metadata_uri = metadata_uri;
+        }
+        if let Some(is_public) = is_public {
+            music_track.is_public = is_public;
+        }
+
+        // Grow the account when the new URIs no longer fit, the authority covers the extra rent
+        grow_music_track(
+            music_track,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
+    // Set the URIs or asset ids of the tiers above the base tier, `tier_uris[0]` being tier 1
+    pub fn set_tier_uris(
+        ctx: Context<UpdateMusicTrack>,
+        tier_uris: Vec<String>,
+    ) -> Result<()> {
+        require!(tier_uris.len() <= MAX_ACCESS_TIER as usize, ErrorCode::InvalidTier);
+        for uri in tier_uris.iter() {
+            require!(uri.len() <= MAX_TRACK_URI_LEN, ErrorCode::TrackUriTooLong);
         }
+
+        let music_track = &mut ctx.accounts.music_track;
+        music_track.tier_uris = tier_uris;
+
+        grow_music_track(
+            music_track,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
+    // Assign the access tier holders of an authorized collection or mint get
+    pub fn set_collection_tier(
+        ctx: Context<UpdateTrackAuthorization>,
+        collection_mint: Pubkey,
+        tier: u8,
+    ) -> Result<()> {
+        require!(tier <= MAX_ACCESS_TIER, ErrorCode::InvalidTier);
         
-        // If track is public, allow playback without NFT verification
-        if music_track.is_public {
-            // Cache the authorization if cache account provided
-            if let Some(user_auth) = &mut ctx.accounts.user_auth {
-                user_auth.user = user.key();
-                user_auth.track_id = music_track.track_id.clone();
-                user_auth.expires_at = Clock::get()?.unix_timestamp + 3600; // 1 hour expiration
-            }
-            return Ok(());
+        let music_track = &mut ctx.accounts.music_track;
+        require!(
+            music_track.authorized_collections.contains(&collection_mint),
+            ErrorCode::CollectionNotAuthorized
+        );
+        music_track.collection_tiers.retain(|entry| entry.collection != collection_mint);
+        if tier != BASE_ACCESS_TIER {
+            music_track.collection_tiers.push(CollectionTier { collection: collection_mint, tier });
         }
         
-        // Verify the metadata program is legitimate
-        let expected_metadata_program = 
-            Pubkey::from_str(METADATA_PROGRAM_ID).unwrap();
+        Ok(())
+    }
+
+    // Set how long authorizations for a track stay cached
+    pub fn set_track_auth_ttl(
+        ctx: Context<UpdateTrackAuthorization>,
+        auth_ttl: i64,
+    ) -> Result<()> {
+        require!(auth_ttl > 0 && auth_ttl <= MAX_AUTH_TTL, ErrorCode::InvalidAuthTtl);
+        ctx.accounts.music_track.auth_ttl = auth_ttl;
+        
+        Ok(())
+    }
+    
+    // Set the authorization TTL given to newly registered tracks
+    pub fn set_default_auth_ttl(
+        ctx: Context<UpdateAuthRegistry>,
+        default_auth_ttl: i64,
+    ) -> Result<()> {
         require!(
-            ctx.accounts.metadata_program.key() == expected_metadata_program,
-            ErrorCode::InvalidMetadataProgram
+            default_auth_ttl > 0 && default_auth_ttl <= MAX_AUTH_TTL,
+            ErrorCode::InvalidAuthTtl
         );
+        ctx.accounts.auth_registry.default_auth_ttl = default_auth_ttl;
         
-        // Check if user has provided a token account
-        if let Some(user_token_account) = &ctx.accounts.user_token_account {
-            // Verify the token account belongs to the user
-            // In SPL tokens, the 'owner' field is actually the authority who can transfer the tokens
-            require!(
-                user_token_account.owner == user.key(),
-                ErrorCode::InvalidNFTOwnership
-            );
-            
-            require!(
-                user_token_account.amount == 1,
-                ErrorCode::InvalidNFTOwnership
-            );
-            
-            // Verify the token isn't frozen
-            require!(
-                !user_token_account.is_frozen(),
-                ErrorCode::InvalidNFTOwnership
+        Ok(())
+    }
+    
+    // Remove a music track, returning its rent to the authority
+    pub fn close_music_track(ctx: Context<CloseMusicTrack>) -> Result<()> {
+        let registry = &mut ctx.accounts.auth_registry;
+        registry.track_count = registry.track_count
+            .checked_sub(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+    
+    // Add function to add authorized collection to a track
+    pub fn add_authorized_collection(
+        ctx: Context<UpdateTrackAuthorization>,
+        collection_mint: Pubkey,
+    ) -> Result<()> {
+        let music_track = &mut ctx.accounts.music_track;
+        
+        // Ensure only the track authority can add collections
+        require!(
+            music_track.authority == ctx.accounts.authority.key(),
+            ErrorCode::UnauthorizedPlayback
+        );
+        
+        // Add the collection to authorized list if not already present
+        music_track.authorize_collection(collection_mint)
+    }
+    
+    // Authorize several collections at once
+    pub fn add_authorized_collections(
+        ctx: Context<UpdateTrackAuthorization>,
+        collection_mints: Vec<Pubkey>,
+    ) -> Result<()> {
+        let music_track = &mut ctx.accounts.music_track;
+        for collection_mint in collection_mints {
+            music_track.authorize_collection(collection_mint)?;
+        }
+        
+        Ok(())
+    }
+    
+    // Revoke a collection. Caches granted through it stop being honored
+    pub fn remove_authorized_collection(
+        ctx: Context<UpdateTrackAuthorization>,
+        collection_mint: Pubkey,
+    ) -> Result<()> {
+        let music_track = &mut ctx.accounts.music_track;
+        let index = music_track.authorized_collections
+            .iter()
+            .position(|key| *key == collection_mint)
+            .ok_or(ErrorCode::CollectionNotAuthorized)?;
+        music_track.authorized_collections.remove(index);
+        music_track.collection_tiers.retain(|entry| entry.collection != collection_mint);
+        
+        Ok(())
+    }
+    
+    // Replace the whole authorized collection list
+    pub fn set_authorized_collections(
+        ctx: Context<UpdateTrackAuthorization>,
+        collection_mints: Vec<Pubkey>,
+    ) -> Result<()> {
+        let music_track = &mut ctx.accounts.music_track;
+        music_track.authorized_collections.clear();
+        for collection_mint in collection_mints {
+            music_track.authorize_collection(collection_mint)?;
+        }
+        let MusicTrack { authorized_collections, collection_tiers, .. } = &mut **music_track;
+        collection_tiers.retain(|entry| authorized_collections.contains(&entry.collection));
+        
+        Ok(())
+    }
+    
+    // Schedule a release. Before `release_at` only `prerelease_collections` can play, and
+    // from `holders_only_until` on the track is public. Zero disables either timestamp
+    pub fn set_release_window(
+        ctx: Context<UpdateTrackAuthorization>,
+        release_at: i64,
+        holders_only_until: i64,
+        prerelease_collections: Vec<Pubkey>,
+    ) -> Result<()> {
+        require!(
+            holders_only_until == 0 || holders_only_until >= release_at,
+            ErrorCode::InvalidReleaseWindow
+        );
+        require!(
+            prerelease_collections.len() <= MAX_PRERELEASE_COLLECTIONS,
+            ErrorCode::TooManyCollections
+        );
+        
+        let music_track = &mut ctx.accounts.music_track;
+        music_track.release_at = release_at;
+        music_track.holders_only_until = holders_only_until;
+        music_track.prerelease_collections = prerelease_collections;
+        
+        Ok(())
+    }
+    
+    // Gate a track on holding at least `min_amount` of a fungible token, or update the minimum
+    pub fn set_token_gate(
+        ctx: Context<UpdateTrackAuthorization>,
+        mint: Pubkey,
+        min_amount: u64,
+    ) -> Result<()> {
+        require!(min_amount > 0, ErrorCode::InvalidTokenGate);
+        
+        let music_track = &mut ctx.accounts.music_track;
+        if let Some(token_gate) = music_track.token_gates.iter_mut().find(|gate| gate.mint == mint) {
+            token_gate.min_amount = min_amount;
+        } else {
+            require!(
+                music_track.token_gates.len() < MAX_TOKEN_GATES,
+                ErrorCode::TooManyTokenGates
             );
+            music_track.token_gates.push(TokenGate { mint, min_amount });
+        }
+        
+        Ok(())
+    }
+    
+    // Remove a fan token gate. Caches granted through it stop being honored
+    pub fn remove_token_gate(
+        ctx: Context<UpdateTrackAuthorization>,
+        mint: Pubkey,
+    ) -> Result<()> {
+        let music_track = &mut ctx.accounts.music_track;
+        let index = music_track.token_gates
+            .iter()
+            .position(|gate| gate.mint == mint)
+            .ok_or(ErrorCode::InvalidTokenGate)?;
+        music_track.token_gates.remove(index);
+        
+        Ok(())
+    }
+    
+    // Sell timed access to a track. Payments in `payment_mint` are split by the
+    // royalty config of `royalty_mint`, or go entirely to the track authority without one
+    pub fn set_access_schedule(
+        ctx: Context<UpdateTrackAuthorization>,
+        payment_mint: Pubkey,
+        royalty_mint: Pubkey,
+        access_tiers: Vec<AccessTier>,
+    ) -> Result<()> {
+        require!(access_tiers.len() <= MAX_ACCESS_TIERS, ErrorCode::TooManyAccessTiers);
+        for tier in access_tiers.iter() {
+            require!(tier.price > 0 && tier.duration_sec > 0, ErrorCode::InvalidAccessTier);
+        }
+        
+        let music_track = &mut ctx.accounts.music_track;
+        music_track.access_payment_mint = payment_mint;
+        music_track.royalty_mint = royalty_mint;
+        music_track.access_tiers = access_tiers;
+        
+        Ok(())
+    }
+    
+    // Buy or extend a time pass for a track. `price` must match the tier to guard against
+    // schedule changes, royalty recipient token accounts go in remaining accounts
+    pub fn purchase_access<'info>(
+        ctx: Context<'_, '_, '_, 'info, PurchaseAccess<'info>>,
+        tier_index: u8,
+        price: u64,
+    ) -> Result<()> {
+        let music_track = &ctx.accounts.music_track;
+        let tier = music_track.access_tiers
+            .get(tier_index as usize)
+            .ok_or(ErrorCode::InvalidAccessTier)?;
+        require!(tier.price == price, ErrorCode::InvalidAccessTier);
+        
+        let royalty_config = load_royalty_config(&ctx.accounts.royalty_config, ctx.program_id)?;
+        pay_with_royalties(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.buyer_payment_account,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.artist_payment_account,
+            royalty_config.as_ref(),
+            ctx.remaining_accounts,
+            price,
+            &[],
+        )?;
+        
+        // Extend from the current expiry if the pass is still running
+        let now = Clock::get()?.unix_timestamp;
+        let access_pass = &mut ctx.accounts.access_pass;
+        access_pass.user = ctx.accounts.buyer.key();
+        access_pass.music_track = music_track.key();
+        access_pass.expires_at = access_pass.expires_at
+            .max(now)
+            .checked_add(tier.duration_sec)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        access_pass.bump = *ctx.bumps.get("access_pass").unwrap();
+        
+        Ok(())
+    }
+    
+    // Offer one subscription covering every track of the calling artist
+    pub fn create_subscription_plan(
+        ctx: Context<CreateSubscriptionPlan>,
+        price: u64,
+        period_sec: i64,
+    ) -> Result<()> {
+        require!(price > 0 && period_sec > 0, ErrorCode::InvalidSubscriptionPlan);
+        
+        let plan = &mut ctx.accounts.subscription_plan;
+        plan.artist = ctx.accounts.artist.key();
+        plan.payment_mint = ctx.accounts.payment_mint.key();
+        plan.artist_payment_account = ctx.accounts.artist_payment_account.key();
+        plan.price = price;
+        plan.period_sec = period_sec;
+        plan.bump = *ctx.bumps.get("subscription_plan").unwrap();
+        
+        Ok(())
+    }
+    
+    // Change the price or period for future subscribe and renew calls
+    pub fn update_subscription_plan(
+        ctx: Context<UpdateSubscriptionPlan>,
+        price: u64,
+        period_sec: i64,
+    ) -> Result<()> {
+        require!(price > 0 && period_sec > 0, ErrorCode::InvalidSubscriptionPlan);
+        
+        let plan = &mut ctx.accounts.subscription_plan;
+        plan.price = price;
+        plan.period_sec = period_sec;
+        
+        Ok(())
+    }
+    
+    // Subscribe to an artist for one period. `price` must match the plan
+    pub fn subscribe(ctx: Context<Subscribe>, price: u64) -> Result<()> {
+        require!(ctx.accounts.subscription_plan.price == price, ErrorCode::InvalidSubscriptionPlan);
+        
+        token::transfer(ctx.accounts.to_pay_artist_context(), price)?;
+        
+        let plan = &ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.subscriber = ctx.accounts.subscriber.key();
+        subscription.artist = plan.artist;
+        subscription.subscription_plan = plan.key();
+        subscription.expires_at = Clock::get()?.unix_timestamp
+            .checked_add(plan.period_sec)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        subscription.bump = *ctx.bumps.get("subscription").unwrap();
+        
+        Ok(())
+    }
+    
+    // Pay for another period, extending from the current expiry if still active
+    pub fn renew_subscription(ctx: Context<RenewSubscription>, price: u64) -> Result<()> {
+        require!(ctx.accounts.subscription_plan.price == price, ErrorCode::InvalidSubscriptionPlan);
+        
+        token::transfer(ctx.accounts.to_pay_artist_context(), price)?;
+        
+        let period_sec = ctx.accounts.subscription_plan.period_sec;
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.expires_at = subscription.expires_at
+            .max(Clock::get()?.unix_timestamp)
+            .checked_add(period_sec)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        Ok(())
+    }
+    
+    // End a subscription and reclaim its rent. Remaining time is forfeited
+    pub fn cancel_subscription(_ctx: Context<CancelSubscription>) -> Result<()> {
+        Ok(())
+    }
+    
+    // Expire a cache whose grant was revoked. Anyone can call this
+    pub fn invalidate_user_auth_cache(ctx: Context<InvalidateUserAuthCache>) -> Result<()> {
+        require!(
+            !ctx.accounts.user_auth.is_still_granted(&ctx.accounts.music_track, Clock::get()?.unix_timestamp),
+            ErrorCode::AuthorizationStillValid
+        );
+        ctx.accounts.user_auth.expires_at = 0;
+        
+        Ok(())
+    }
+    
+    // Add function to verify playback authorization
+    // Fails unless the user may play the track, the grant is also set as return data
+    pub fn verify_playback_authorization(
+        ctx: Context<VerifyPlaybackAuthorization>,
+    ) -> Result<PlaybackAuthorization> {
+        let authorization = authorize_playback(ctx.accounts, ctx.program_id)?;
+        match authorization.reason {
+            PlaybackReason::NotReleased => Err(ErrorCode::TrackNotReleased.into()),
+            PlaybackReason::Denied => Err(ErrorCode::UnauthorizedPlayback.into()),
+            _ => Ok(authorization),
+        }
+    }
+    
+    // Same checks as verify_playback_authorization, but a denial is returned rather than failing
+    // so programs calling in over CPI can branch on it
+    pub fn check_playback_authorization(
+        ctx: Context<VerifyPlaybackAuthorization>,
+    ) -> Result<PlaybackAuthorization> {
+        authorize_playback(ctx.accounts, ctx.program_id)
+    }
+    
+    // Let `delegate` play with the caller's token until `expires_at`, for one track
+    // or for every track when `music_track` is the default key
+    pub fn delegate_listening(
+        ctx: Context<DelegateListening>,
+        delegate: Pubkey,
+        music_track: Pubkey,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(expires_at > Clock::get()?.unix_timestamp, ErrorCode::InvalidDelegation);
+        
+        let delegation = &mut ctx.accounts.delegation;
+        delegation.holder = ctx.accounts.holder.key();
+        delegation.delegate = delegate;
+        delegation.mint = ctx.accounts.holder_token_account.mint;
+        delegation.music_track = music_track;
+        delegation.expires_at = expires_at;
+        delegation.bump = *ctx.bumps.get("delegation").unwrap();
+        
+        Ok(())
+    }
+    
+    // Revoke a listening delegation before it expires. The delegate's authorization caches are
+    // passed as remaining accounts and expired, so a cached grant doesn't outlive the delegation
+    pub fn revoke_listening_delegation<'info>(
+        ctx: Context<'_, '_, '_, 'info, RevokeListeningDelegation<'info>>,
+    ) -> Result<()> {
+        let delegate = ctx.accounts.delegation.delegate;
+        for cache_info in ctx.remaining_accounts.iter() {
+            let mut user_auth = Account::<UserAuthCache>::try_from(cache_info)?;
+            require!(user_auth.user == delegate, ErrorCode::InvalidAuthCache);
+            user_auth.expires_at = 0;
+            user_auth.exit(ctx.program_id)?;
+        }
+        
+        Ok(())
+    }
+    
+    // Let an ephemeral key verify playback for the caller until `expires_at`, limited to the
+    // given tracks and artists, or every track when both are empty. `lamport_budget` is sent
+    // to the key to pay its transaction fees
+    pub fn create_session_key(
+        ctx: Context<CreateSessionKey>,
+        music_tracks: Vec<Pubkey>,
+        artists: Vec<Pubkey>,
+        expires_at: i64,
+        lamport_budget: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            expires_at > now && expires_at <= now + MAX_SESSION_DURATION,
+            ErrorCode::InvalidSessionKey
+        );
+        require!(music_tracks.len() + artists.len() <= MAX_SESSION_SCOPE, ErrorCode::InvalidSessionKey);
+        
+        if lamport_budget > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.user.to_account_info(),
+                        to: ctx.accounts.session_key.to_account_info(),
+                    },
+                ),
+                lamport_budget,
+            )?;
+        }
+        
+        let session = &mut ctx.accounts.session;
+        session.user = ctx.accounts.user.key();
+        session.session_key = ctx.accounts.session_key.key();
+        session.music_tracks = music_tracks;
+        session.artists = artists;
+        session.expires_at = expires_at;
+        session.lamport_budget = lamport_budget;
+        session.bump = *ctx.bumps.get("session").unwrap();
+        
+        Ok(())
+    }
+    
+    // Revoke a session key before it expires
+    pub fn revoke_session_key(_ctx: Context<RevokeSessionKey>) -> Result<()> {
+        Ok(())
+    }
+    
+    // Open the payout pool for a play epoch, paid out in `payment_mint`
+    pub fn create_play_epoch(ctx: Context<CreatePlayEpoch>, epoch: u64) -> Result<()> {
+        require!(epoch >= current_play_epoch()?, ErrorCode::PlayEpochEnded);
+        
+        let play_epoch = &mut ctx.accounts.play_epoch;
+        play_epoch.epoch = epoch;
+        play_epoch.payment_mint = ctx.accounts.payment_mint.key();
+        play_epoch.total_plays = 0;
+        play_epoch.funded_amount = 0;
+        play_epoch.claimed_plays = 0;
+        play_epoch.claimed_amount = 0;
+        play_epoch.bump = *ctx.bumps.get("play_epoch").unwrap();
+        
+        Ok(())
+    }
+    
+    // Add revenue to a play epoch's pool. Anyone can fund it, e.g. the platform
+    // or an artist forwarding subscription revenue, until the epoch ends
+    pub fn fund_play_epoch(ctx: Context<FundPlayEpoch>, amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.play_epoch.epoch >= current_play_epoch()?,
+            ErrorCode::PlayEpochEnded
+        );
+        
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.funder_payment_account.to_account_info(),
+                    to: ctx.accounts.play_epoch_vault.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        
+        let play_epoch = &mut ctx.accounts.play_epoch;
+        play_epoch.funded_amount = play_epoch.funded_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        Ok(())
+    }
+    
+    // Create the play counter of a track for the current epoch ahead of the attestor's first report
+    pub fn create_track_plays(ctx: Context<CreateTrackPlays>) -> Result<()> {
+        require!(
+            ctx.accounts.play_epoch.epoch == current_play_epoch()?,
+            ErrorCode::InvalidPlayEpoch
+        );
+        
+        let track_plays = &mut ctx.accounts.track_plays;
+        track_plays.music_track = ctx.accounts.music_track.key();
+        track_plays.epoch = ctx.accounts.play_epoch.epoch;
+        track_plays.plays = 0;
+        track_plays.claimed = false;
+        track_plays.bump = *ctx.bumps.get("track_plays").unwrap();
+        
+        Ok(())
+    }
+    
+    // Let the registry authority choose who can attest plays counted off-chain
+    pub fn set_play_attestor(ctx: Context<UpdateAuthRegistry>, play_attestor: Pubkey) -> Result<()> {
+        ctx.accounts.auth_registry.play_attestor = play_attestor;
+        
+        Ok(())
+    }
+    
+    // Record plays of a track in the current epoch on behalf of the attestor
+    pub fn attest_plays(ctx: Context<AttestPlays>, plays: u64) -> Result<()> {
+        require!(
+            ctx.accounts.play_epoch.epoch == current_play_epoch()?,
+            ErrorCode::InvalidPlayEpoch
+        );
+        
+        let track_plays = &mut ctx.accounts.track_plays;
+        if track_plays.music_track == Pubkey::default() {
+            track_plays.music_track = ctx.accounts.music_track.key();
+            track_plays.epoch = ctx.accounts.play_epoch.epoch;
+            track_plays.bump = *ctx.bumps.get("track_plays").unwrap();
+        }
+        track_plays.plays = track_plays.plays
+            .checked_add(plays)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        let play_epoch = &mut ctx.accounts.play_epoch;
+        play_epoch.total_plays = play_epoch.total_plays
+            .checked_add(plays)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        Ok(())
+    }
+    
+    // Pay a track its share of an ended epoch's pool, pro-rata to its plays, through the
+    // track's royalty config. The last track to claim also takes the rounding dust.
+    // Royalty recipient token accounts go in remaining accounts
+    pub fn claim_play_royalties<'info>(
+        ctx: Context<'_, '_, '_, 'info, ClaimPlayRoyalties<'info>>,
+    ) -> Result<()> {
+        let plays = ctx.accounts.track_plays.plays;
+        let play_epoch = &mut ctx.accounts.play_epoch;
+        require!(play_epoch.epoch < current_play_epoch()?, ErrorCode::PlayEpochNotEnded);
+        require!(play_epoch.total_plays > 0 && plays > 0, ErrorCode::NoPlaysCounted);
+        
+        let claimed_plays = play_epoch.claimed_plays
+            .checked_add(plays)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let share = if claimed_plays == play_epoch.total_plays {
+            play_epoch.funded_amount
+                .checked_sub(play_epoch.claimed_amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+        } else {
+            (play_epoch.funded_amount as u128)
+                .checked_mul(plays as u128)
+                .and_then(|amount| amount.checked_div(play_epoch.total_plays as u128))
+                .ok_or(ErrorCode::ArithmeticOverflow)? as u64
+        };
+        play_epoch.claimed_plays = claimed_plays;
+        play_epoch.claimed_amount = play_epoch.claimed_amount
+            .checked_add(share)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        let play_epoch = &ctx.accounts.play_epoch;
+        if share > 0 {
+            let epoch_bytes = play_epoch.epoch.to_le_bytes();
+            let seeds = &[
+                PLAY_EPOCH_SEED,
+                play_epoch.payment_mint.as_ref(),
+                epoch_bytes.as_ref(),
+                &[play_epoch.bump],
+            ];
+            let signer = &[&seeds[..]];
             
-            // Check if the NFT mint is in the authorized collections
-            let nft_mint = user_token_account.mint;
-            let mut is_authorized = false;
-            
-            // If we have metadata account info, verify it
-            if let Some(metadata_info) = &ctx.accounts.nft_metadata {
-                // Verify the metadata PDA matches the expected one for this mint
-                let metadata_program_id = ctx.accounts.metadata_program.key();
-                let seeds = &[
-                    b"metadata",
-                    metadata_program_id.as_ref(),
-                    nft_mint.as_ref(),
-                ];
-                let (expected_metadata_key, _) = 
-                    Pubkey::find_program_address(seeds, metadata_program_id);
-                
-                require!(
-                    metadata_info.key() == expected_metadata_key,
-                    ErrorCode::InvalidNFTMetadata
-                );
-                
-                // Check if NFT is in an authorized collection
-                // First, check if NFT mint is directly authorized
-                if music_track.authorized_collections.contains(&nft_mint) {
-                    is_authorized = true;
-                } else {
-                    // More complete implementation would parse metadata here to check collection
-                    // For example:
-                    //
-                    // 1. Parse the metadata (this would require Metaplex's metadata structures)
-                    // 2. Check the collection field in the metadata
-                    // 3. Verify the collection is in our authorized_collections list
-                    //
-                    // This is a simplified authorization for demonstration
-                    
-                    // For collections, we would need to deserialize and check:
-                    // let metadata = mpl_token_metadata::state::Metadata::from_account_info(metadata_info)?;
-                    // if let Some(collection) = metadata.collection {
-                    //    if collection.verified && music_track.authorized_collections.contains(&collection.key) {
-                    //        is_authorized = true;
-                    //    }
-                    // }
-                }
-            }
-            
-            if is_authorized {
-                // Cache the authorization if cache account provided
-                if let Some(user_auth) = &mut ctx.accounts.user_auth {
-                    user_auth.user = user.key();
-                    user_auth.track_id = music_track.track_id.clone();
-                    user_auth.expires_at = Clock::get()?.unix_timestamp + 3600; // 1 hour expiration
-                }
-                return Ok(());
-            }
+            let royalty_config = load_royalty_config(&ctx.accounts.royalty_config, ctx.program_id)?;
+            pay_with_royalties(
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.play_epoch_vault,
+                &ctx.accounts.play_epoch.to_account_info(),
+                &ctx.accounts.artist_payment_account,
+                royalty_config.as_ref(),
+                ctx.remaining_accounts,
+                share,
+                signer,
+            )?;
         }
         
-        // If we reach here, the user is not authorized
-        Err(ErrorCode::UnauthorizedPlayback.into())
+        ctx.accounts.track_plays.claimed = true;
+        
+        Ok(())
     }
     
     // Add function to create an authorization cache for a user
@@ -367,12 +1594,40 @@ pub mod anchor_auction {
         track_id: String,
     ) -> Result<()> {
         // Initialize with default values, actual authorization happens in verify_playback
+        ctx.accounts.user_auth.version = USER_AUTH_CACHE_VERSION;
         ctx.accounts.user_auth.user = ctx.accounts.user.key();
         ctx.accounts.user_auth.track_id = track_id;
         ctx.accounts.user_auth.expires_at = 0; // Will be set during verification
+        ctx.accounts.user_auth.granted_via = Pubkey::default();
+        ctx.accounts.user_auth.tier = BASE_ACCESS_TIER;
+        ctx.accounts.user_auth.payer = ctx.accounts.payer.key();
+        ctx.accounts.user_auth.created_at = Clock::get()?.unix_timestamp;
+        
+        Ok(())
+    }
+    
+    // Close a user's own authorization cache, returning rent to whoever paid for it
+    pub fn close_user_auth_cache(_ctx: Context<CloseUserAuthCache>) -> Result<()> {
+        Ok(())
+    }
+    
+    // Close a cache that has been idle past the grace period. Anyone can call this
+    pub fn sweep_user_auth_cache(ctx: Context<SweepUserAuthCache>) -> Result<()> {
+        let user_auth = &ctx.accounts.user_auth;
+        let idle_since = user_auth.expires_at.max(user_auth.created_at);
+        require!(
+            Clock::get()?.unix_timestamp > idle_since.saturating_add(AUTH_CACHE_SWEEP_GRACE),
+            ErrorCode::AuthCacheNotSweepable
+        );
         
         Ok(())
     }
+    
+    // Close a cache created under the old track id seeds, which verification no longer reads.
+    // Anyone can call this, the rent goes back to the user who paid for it
+    pub fn close_legacy_user_auth_cache(_ctx: Context<CloseLegacyUserAuthCache>) -> Result<()> {
+        Ok(())
+    }
 
     // Create royalty configuration for an NFT
     pub fn create_royalty_config(
@@ -426,6 +1681,18 @@ pub mod anchor_auction {
             ErrorCode::ImmutableRoyaltyConfig
         );
         
+        // If an NFT token account was provided, it must hold the NFT for the authority
+        if let Some(nft_token_account) = &ctx.accounts.nft_token_account {
+            require!(
+                nft_token_account.mint == ctx.accounts.nft_mint.key(),
+                ErrorCode::TokenMintMismatch
+            );
+            require!(
+                nft_token_account.owner == ctx.accounts.authority.key() && nft_token_account.amount > 0,
+                ErrorCode::InvalidNFTOwnership
+            );
+        }
+        
         // Validate royalty parameters
         require!(
             total_basis_points <= MAX_ROYALTY_BASIS_POINTS,
@@ -455,219 +1722,652 @@ pub mod anchor_auction {
         Ok(())
     }
     
-    // Process a sale with royalty distribution - optimized version
-    pub fn process_sale_with_royalties(
-        ctx: Context<ProcessSale>,
-        sale_price: u64,
-    ) -> Result<()> {
-        let royalty_config = &ctx.accounts.royalty_config;
-        
-        // Early return if no royalty is configured
-        if royalty_config.total_basis_points == 0 || royalty_config.recipients.is_empty() {
-            // Direct transfer to seller, no royalties
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.buyer_payment_account.to_account_info(),
-                        to: ctx.accounts.seller_payment_account.to_account_info(),
-                        authority: ctx.accounts.buyer.to_account_info(),
-                    },
-                ),
-                sale_price,
-            )?;
-            return Ok(());
-        }
-        
-        // Calculate total royalty amount
-        let total_royalty_amount = calculate_royalty_amount(
-            sale_price, 
-            royalty_config.total_basis_points
+    // List an NFT at a fixed price, the NFT is escrowed in a vault owned by the listing PDA
+    pub fn list(ctx: Context<List>, price: u64) -> Result<()> {
+        require!(price > 0, ErrorCode::SalePriceTooLow);
+
+        let listing = &mut ctx.accounts.listing;
+        listing.seller = ctx.accounts.seller.key();
+        listing.nft_mint = ctx.accounts.nft_mint.key();
+        listing.payment_mint = ctx.accounts.payment_mint.key();
+        listing.seller_payment_account = ctx.accounts.seller_payment_account.key();
+        listing.price = price;
+        listing.bump = *ctx.bumps.get("listing").unwrap();
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.seller_nft_account.to_account_info(),
+                    to: ctx.accounts.listing_vault.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
         )?;
-        
-        // If royalty amount is 0, skip royalty distribution
-        if total_royalty_amount == 0 {
-            // Direct transfer to seller
+
+        Ok(())
+    }
+
+    // Take a listed NFT back out of escrow
+    pub fn delist(ctx: Context<Delist>) -> Result<()> {
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let seeds = &[
+            LISTING_SEED,
+            nft_mint.as_ref(),
+            &[ctx.accounts.listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.listing_vault.to_account_info(),
+                    to: ctx.accounts.seller_nft_account.to_account_info(),
+                    authority: ctx.accounts.listing.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        token::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.listing_vault.to_account_info(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.listing.to_account_info(),
+                },
+                signer,
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    // Buy a listed NFT: pay royalties and the seller, then deliver the NFT in the same instruction.
+    // `price` must match the listing so a relisted NFT can't be bought at a different price.
+    pub fn buy<'info>(ctx: Context<'_, '_, '_, 'info, Buy<'info>>, price: u64) -> Result<()> {
+        require!(ctx.accounts.listing.price == price, ErrorCode::InvalidListing);
+
+        let royalty_config = load_royalty_config(&ctx.accounts.royalty_config, ctx.program_id)?;
+        pay_with_royalties(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.buyer_payment_account,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.seller_payment_account,
+            royalty_config.as_ref(),
+            ctx.remaining_accounts,
+            price,
+            &[],
+        )?;
+
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let seeds = &[
+            LISTING_SEED,
+            nft_mint.as_ref(),
+            &[ctx.accounts.listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.listing_vault.to_account_info(),
+                    to: ctx.accounts.buyer_nft_account.to_account_info(),
+                    authority: ctx.accounts.listing.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        token::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.listing_vault.to_account_info(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.listing.to_account_info(),
+                },
+                signer,
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    // Offer `price` for an NFT that isn't listed, the payment is escrowed until the offer
+    // is accepted or cancelled
+    pub fn make_offer(ctx: Context<MakeOffer>, price: u64, expires_at: i64) -> Result<()> {
+        require!(price > 0, ErrorCode::SalePriceTooLow);
+        require!(expires_at > Clock::get()?.unix_timestamp, ErrorCode::InvalidOffer);
+
+        let offer = &mut ctx.accounts.offer;
+        offer.buyer = ctx.accounts.buyer.key();
+        offer.nft_mint = ctx.accounts.nft_mint.key();
+        offer.payment_mint = ctx.accounts.payment_mint.key();
+        offer.price = price;
+        offer.expires_at = expires_at;
+        offer.bump = *ctx.bumps.get("offer").unwrap();
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.buyer_payment_account.to_account_info(),
+                    to: ctx.accounts.offer_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            ),
+            price,
+        )?;
+
+        Ok(())
+    }
+
+    // Withdraw an offer and get the escrowed payment back, possible at any time before acceptance
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let buyer = ctx.accounts.buyer.key();
+        let seeds = &[
+            OFFER_SEED,
+            nft_mint.as_ref(),
+            buyer.as_ref(),
+            &[ctx.accounts.offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.offer_vault.to_account_info(),
+                    to: ctx.accounts.buyer_payment_account.to_account_info(),
+                    authority: ctx.accounts.offer.to_account_info(),
+                },
+                signer,
+            ),
+            ctx.accounts.offer_vault.amount,
+        )?;
+
+        token::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.offer_vault.to_account_info(),
+                    destination: ctx.accounts.buyer.to_account_info(),
+                    authority: ctx.accounts.offer.to_account_info(),
+                },
+                signer,
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    // Accept an offer as the NFT owner: the NFT goes to the buyer and the escrowed payment is
+    // split through the NFT's royalty config. `price` must match the offer being accepted.
+    pub fn accept_offer<'info>(ctx: Context<'_, '_, '_, 'info, AcceptOffer<'info>>, price: u64) -> Result<()> {
+        require!(ctx.accounts.offer.price == price, ErrorCode::InvalidOffer);
+        require!(
+            ctx.accounts.offer.expires_at > Clock::get()?.unix_timestamp,
+            ErrorCode::OfferExpired
+        );
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.seller_nft_account.to_account_info(),
+                    to: ctx.accounts.buyer_nft_account.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let buyer = ctx.accounts.buyer.key();
+        let seeds = &[
+            OFFER_SEED,
+            nft_mint.as_ref(),
+            buyer.as_ref(),
+            &[ctx.accounts.offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let royalty_config = load_royalty_config(&ctx.accounts.royalty_config, ctx.program_id)?;
+        pay_with_royalties(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.offer_vault,
+            &ctx.accounts.offer.to_account_info(),
+            &ctx.accounts.seller_payment_account,
+            royalty_config.as_ref(),
+            ctx.remaining_accounts,
+            price,
+            signer,
+        )?;
+
+        // Anything sent to the vault on top of the offer goes back to the buyer so the vault can close
+        ctx.accounts.offer_vault.reload()?;
+        if ctx.accounts.offer_vault.amount > 0 {
             token::transfer(
-                CpiContext::new(
+                CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
                     Transfer {
-                        from: ctx.accounts.buyer_payment_account.to_account_info(),
-                        to: ctx.accounts.seller_payment_account.to_account_info(),
-                        authority: ctx.accounts.buyer.to_account_info(),
+                        from: ctx.accounts.offer_vault.to_account_info(),
+                        to: ctx.accounts.buyer_payment_account.to_account_info(),
+                        authority: ctx.accounts.offer.to_account_info(),
                     },
+                    signer,
                 ),
-                sale_price,
+                ctx.accounts.offer_vault.amount,
             )?;
-            return Ok(());
         }
-        
-        // Verify buyer has enough funds for the entire transaction
+
+        token::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.offer_vault.to_account_info(),
+                    destination: ctx.accounts.buyer.to_account_info(),
+                    authority: ctx.accounts.offer.to_account_info(),
+                },
+                signer,
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    // Offer `price` for up to `max_items` NFTs of a verified collection. The full amount is
+    // escrowed and any holder of a verified member can fill the offer one NFT at a time.
+    pub fn make_collection_offer(
+        ctx: Context<MakeCollectionOffer>,
+        price: u64,
+        max_items: u32,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(price > 0, ErrorCode::SalePriceTooLow);
+        require!(max_items > 0, ErrorCode::InvalidOffer);
+        require!(expires_at > Clock::get()?.unix_timestamp, ErrorCode::InvalidOffer);
+
+        let deposit = price
+            .checked_mul(max_items as u64)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let collection_offer = &mut ctx.accounts.collection_offer;
+        collection_offer.buyer = ctx.accounts.buyer.key();
+        collection_offer.collection_mint = ctx.accounts.collection_mint.key();
+        collection_offer.payment_mint = ctx.accounts.payment_mint.key();
+        collection_offer.price = price;
+        collection_offer.max_items = max_items;
+        collection_offer.filled = 0;
+        collection_offer.expires_at = expires_at;
+        collection_offer.bump = *ctx.bumps.get("collection_offer").unwrap();
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.buyer_payment_account.to_account_info(),
+                    to: ctx.accounts.offer_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            ),
+            deposit,
+        )?;
+
+        Ok(())
+    }
+
+    // Withdraw a collection offer and get back whatever is left in escrow
+    pub fn cancel_collection_offer(ctx: Context<CancelCollectionOffer>) -> Result<()> {
+        let collection_mint = ctx.accounts.collection_mint.key();
+        let buyer = ctx.accounts.buyer.key();
+        let seeds = &[
+            COLLECTION_OFFER_SEED,
+            collection_mint.as_ref(),
+            buyer.as_ref(),
+            &[ctx.accounts.collection_offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.offer_vault.to_account_info(),
+                    to: ctx.accounts.buyer_payment_account.to_account_info(),
+                    authority: ctx.accounts.collection_offer.to_account_info(),
+                },
+                signer,
+            ),
+            ctx.accounts.offer_vault.amount,
+        )?;
+
+        token::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.offer_vault.to_account_info(),
+                    destination: ctx.accounts.buyer.to_account_info(),
+                    authority: ctx.accounts.collection_offer.to_account_info(),
+                },
+                signer,
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    // Sell one NFT of the collection into a collection offer. Membership is checked against the
+    // verified collection in the NFT's metadata and the payment goes through its royalty config.
+    pub fn fill_collection_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, FillCollectionOffer<'info>>,
+        price: u64,
+    ) -> Result<()> {
+        let collection_offer = &ctx.accounts.collection_offer;
+        require!(collection_offer.price == price, ErrorCode::InvalidOffer);
         require!(
-            ctx.accounts.buyer_payment_account.amount >= sale_price,
-            ErrorCode::InsufficientFunds
+            collection_offer.expires_at > Clock::get()?.unix_timestamp,
+            ErrorCode::OfferExpired
         );
-        
-        // Get the payment token mint for validating recipient accounts
-        let payment_mint = ctx.accounts.buyer_payment_account.mint;
-        
-        // Get the remaining accounts for royalty recipients
-        let remaining_accounts = ctx.remaining_accounts;
         require!(
-            remaining_accounts.len() >= royalty_config.recipients.len(),
-            ErrorCode::MissingExpectedAccount
+            collection_offer.filled < collection_offer.max_items,
+            ErrorCode::CollectionOfferFilled
         );
-        
-        // Verify seller token account uses the same mint
+
+        let collection = verified_collection(&ctx.accounts.nft_metadata, &ctx.accounts.nft_mint.key())?;
         require!(
-            ctx.accounts.seller_payment_account.mint == payment_mint,
-            ErrorCode::TokenMintMismatch
+            collection == Some(collection_offer.collection_mint),
+            ErrorCode::NotInCollection
         );
-        
-        // Pre-validate all recipient accounts to avoid partial execution
-        let mut recipient_account_map = std::collections::HashMap::new();
-        for (i, recipient) in royalty_config.recipients.iter().enumerate() {
-            let recipient_account_info = &remaining_accounts[i];
-            
-            // Verify recipient account is a valid token account
-            let recipient_account = Account::<TokenAccount>::try_from(recipient_account_info)?;
-            
-            // Verify recipient token account uses the same mint as the payment
-            require!(
-                recipient_account.mint == payment_mint,
-                ErrorCode::TokenMintMismatch
-            );
-            
-            // Add to map for later use
-            recipient_account_map.insert(recipient.recipient, recipient_account_info);
-        }
-        
-        // Batch process recipients to minimize CPI calls
-        let batched_recipients = batch_royalty_recipients(
-            &royalty_config.recipients,
-            total_royalty_amount,
-            royalty_config.total_basis_points
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.seller_nft_account.to_account_info(),
+                    to: ctx.accounts.buyer_nft_account.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
         )?;
-        
-        // Calculate total royalty amount to be paid
-        let mut total_royalties_paid: u64 = 0;
-        for (_, amount) in &batched_recipients {
-            total_royalties_paid = total_royalties_paid.checked_add(*amount)
-                .ok_or(ProgramError::ArithmeticOverflow)?;
-        }
-        
-        // Calculate seller amount
-        let seller_amount = sale_price.checked_sub(total_royalties_paid)
-            .ok_or(ErrorCode::RoyaltyBasisPointsExceedMax)?;
-        
-        // Now execute the transfers with the batched amounts
-        for (recipient_pubkey, amount) in batched_recipients {
-            if let Some(recipient_account_info) = recipient_account_map.get(&recipient_pubkey) {
-                // Transfer tokens to recipient
-                token::transfer(
-                    CpiContext::new(
-                        ctx.accounts.token_program.to_account_info(),
-                        Transfer {
-                            from: ctx.accounts.buyer_payment_account.to_account_info(),
-                            to: recipient_account_info.to_account_info(),
-                            authority: ctx.accounts.buyer.to_account_info(),
-                        },
-                    ),
-                    amount,
-                )?;
-            } else {
-                return Err(ErrorCode::InvalidRecipientAccount.into());
-            }
-        }
-        
-        // Skip transfer if amount is zero (unlikely but possible edge case)
-        if seller_amount > 0 {
-            // Transfer remaining amount to seller
+
+        let collection_mint = collection_offer.collection_mint;
+        let buyer = ctx.accounts.buyer.key();
+        let seeds = &[
+            COLLECTION_OFFER_SEED,
+            collection_mint.as_ref(),
+            buyer.as_ref(),
+            &[collection_offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let royalty_config = load_royalty_config(&ctx.accounts.royalty_config, ctx.program_id)?;
+        pay_with_royalties(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.offer_vault,
+            &collection_offer.to_account_info(),
+            &ctx.accounts.seller_payment_account,
+            royalty_config.as_ref(),
+            ctx.remaining_accounts,
+            price,
+            signer,
+        )?;
+
+        ctx.accounts.collection_offer.filled += 1;
+
+        Ok(())
+    }
+
+    // Close a collection offer once its last item is bought, returning the rent and anything
+    // left in the vault to the buyer. Anyone can call this
+    pub fn close_filled_collection_offer(ctx: Context<CloseFilledCollectionOffer>) -> Result<()> {
+        let collection_offer = &ctx.accounts.collection_offer;
+        let seeds = &[
+            COLLECTION_OFFER_SEED,
+            collection_offer.collection_mint.as_ref(),
+            collection_offer.buyer.as_ref(),
+            &[collection_offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Fills pay out exactly the offer price, this is only non-zero when tokens were sent to the vault directly
+        if ctx.accounts.offer_vault.amount > 0 {
             token::transfer(
-                CpiContext::new(
+                CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
                     Transfer {
-                        from: ctx.accounts.buyer_payment_account.to_account_info(),
-                        to: ctx.accounts.seller_payment_account.to_account_info(),
-                        authority: ctx.accounts.buyer.to_account_info(),
+                        from: ctx.accounts.offer_vault.to_account_info(),
+                        to: ctx.accounts.buyer_payment_account.to_account_info(),
+                        authority: ctx.accounts.collection_offer.to_account_info(),
                     },
+                    signer,
                 ),
-                seller_amount,
+                ctx.accounts.offer_vault.amount,
             )?;
         }
-        
+
+        token::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.offer_vault.to_account_info(),
+                    destination: ctx.accounts.buyer.to_account_info(),
+                    authority: ctx.accounts.collection_offer.to_account_info(),
+                },
+                signer,
+            ),
+        )?;
+
         Ok(())
     }
-    
-    // Helper function to calculate royalty amount
-    fn calculate_royalty_amount(sale_price: u64, basis_points: u16) -> Result<u64> {
-        // Use checked operations and return appropriate error
-        (sale_price as u128)
-            .checked_mul(basis_points as u128)
-            .and_then(|product| product.checked_div(10000))
-            .map(|quotient| quotient as u64)
-            .ok_or(ProgramError::ArithmeticOverflow.into())
+
+    // Offer an NFT for rent at `daily_price` for up to `max_days`. The NFT is held in a vault until rented
+    pub fn list_rental(ctx: Context<ListRental>, daily_price: u64, max_days: u16) -> Result<()> {
+        require!(daily_price > 0, ErrorCode::SalePriceTooLow);
+        require!(max_days > 0, ErrorCode::InvalidRental);
+
+        let rental = &mut ctx.accounts.rental;
+        rental.owner = ctx.accounts.owner.key();
+        rental.nft_mint = ctx.accounts.nft_mint.key();
+        rental.payment_mint = ctx.accounts.payment_mint.key();
+        rental.owner_payment_account = ctx.accounts.owner_payment_account.key();
+        rental.daily_price = daily_price;
+        rental.max_days = max_days;
+        rental.renter = Pubkey::default();
+        rental.renter_nft_account = Pubkey::default();
+        rental.rented_until = 0;
+        rental.bump = *ctx.bumps.get("rental").unwrap();
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_nft_account.to_account_info(),
+                    to: ctx.accounts.rental_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        Ok(())
     }
-    
-    // Helper function to calculate recipient share with proper rounding
-    fn calculate_recipient_share(
-        total_royalty_amount: u64,
-        recipient_basis_points: u16,
-        total_basis_points: u16
-    ) -> Result<u64> {
-        // Use checked operations and return appropriate error
-        let product = (total_royalty_amount as u128)
-            .checked_mul(recipient_basis_points as u128)
-            .ok_or(ProgramError::ArithmeticOverflow)?;
-        
-        // Round up to avoid dust amounts that can get trapped
-        let divisor = total_basis_points as u128;
-        let quotient = product.checked_div(divisor).ok_or(ProgramError::ArithmeticOverflow)?;
-        
-        // Ensure we're not overflowing u64 when casting back
-        if quotient > u64::MAX as u128 {
-            return Err(ProgramError::ArithmeticOverflow.into());
-        }
-        
-        Ok(quotient as u64)
-    }
-
-    // Add a batch processing function for royalty recipients
-    // This reduces separate CPI calls when possible by combining recipients with same properties
-    fn batch_royalty_recipients(
-        recipients: &[RoyaltyRecipient], 
-        total_royalty_amount: u64,
-        total_basis_points: u16
-    ) -> Result<Vec<(Pubkey, u64)>> {
-        // Create a map to group recipients by their address
-        let mut recipient_map: std::collections::HashMap<Pubkey, u64> = std::collections::HashMap::new();
-        
-        // Process each recipient
-        for recipient in recipients {
-            let recipient_share = calculate_recipient_share(
-                total_royalty_amount,
-                recipient.basis_points,
-                total_basis_points
-            )?;
-            
-            if recipient_share > 0 {
-                // Add to existing entry or create new one
-                *recipient_map.entry(recipient.recipient).or_insert(0) += recipient_share;
-            }
-        }
-        
-        // Convert map to vector for easier iteration
-        let mut result = Vec::with_capacity(recipient_map.len());
-        for (pubkey, amount) in recipient_map {
-            result.push((pubkey, amount));
-        }
-        
-        Ok(result)
+
+    // Rent an NFT for `days`. The renter receives the NFT frozen with the rental PDA as
+    // delegate, so it can be played but not moved. `daily_price` must match the listing,
+    // royalty recipient token accounts go in remaining accounts
+    pub fn rent_nft<'info>(
+        ctx: Context<'_, '_, '_, 'info, RentNft<'info>>,
+        days: u16,
+        daily_price: u64,
+    ) -> Result<()> {
+        let rental = &ctx.accounts.rental;
+        require!(rental.daily_price == daily_price, ErrorCode::InvalidRental);
+        require!(days > 0 && days <= rental.max_days, ErrorCode::InvalidRental);
+
+        let price = daily_price
+            .checked_mul(days as u64)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let royalty_config = load_royalty_config(&ctx.accounts.royalty_config, ctx.program_id)?;
+        pay_with_royalties(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.renter_payment_account,
+            &ctx.accounts.renter.to_account_info(),
+            &ctx.accounts.owner_payment_account,
+            royalty_config.as_ref(),
+            ctx.remaining_accounts,
+            price,
+            &[],
+        )?;
+
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let seeds = &[
+            RENTAL_SEED,
+            nft_mint.as_ref(),
+            &[rental.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.rental_vault.to_account_info(),
+                    to: ctx.accounts.renter_nft_account.to_account_info(),
+                    authority: rental.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        token::approve(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Approve {
+                    to: ctx.accounts.renter_nft_account.to_account_info(),
+                    delegate: rental.to_account_info(),
+                    authority: ctx.accounts.renter.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        toggle_delegated_freeze(
+            FREEZE_DELEGATED_ACCOUNT_INSTRUCTION,
+            &ctx.accounts.metadata_program,
+            &rental.to_account_info(),
+            &ctx.accounts.renter_nft_account.to_account_info(),
+            &ctx.accounts.nft_edition,
+            &ctx.accounts.nft_mint.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            signer,
+        )?;
+
+        let rented_until = Clock::get()?.unix_timestamp
+            .checked_add(days as i64 * SECONDS_PER_DAY)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let rental = &mut ctx.accounts.rental;
+        rental.renter = ctx.accounts.renter.key();
+        rental.renter_nft_account = ctx.accounts.renter_nft_account.key();
+        rental.rented_until = rented_until;
+
+        Ok(())
+    }
+
+    // Return a rented NFT to the vault once the term is over. Anyone can call this
+    pub fn end_rental(ctx: Context<EndRental>) -> Result<()> {
+        require!(
+            ctx.accounts.rental.rented_until <= Clock::get()?.unix_timestamp,
+            ErrorCode::RentalNotEnded
+        );
+
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let seeds = &[
+            RENTAL_SEED,
+            nft_mint.as_ref(),
+            &[ctx.accounts.rental.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        toggle_delegated_freeze(
+            THAW_DELEGATED_ACCOUNT_INSTRUCTION,
+            &ctx.accounts.metadata_program,
+            &ctx.accounts.rental.to_account_info(),
+            &ctx.accounts.renter_nft_account.to_account_info(),
+            &ctx.accounts.nft_edition,
+            &ctx.accounts.nft_mint.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            signer,
+        )?;
+
+        // The rental PDA is still the delegate of the renter's token account
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.renter_nft_account.to_account_info(),
+                    to: ctx.accounts.rental_vault.to_account_info(),
+                    authority: ctx.accounts.rental.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        let rental = &mut ctx.accounts.rental;
+        rental.renter = Pubkey::default();
+        rental.renter_nft_account = Pubkey::default();
+        rental.rented_until = 0;
+
+        Ok(())
     }
 
-    // Add helper to find royalty config PDA for a given mint
-    pub fn find_royalty_config_pda(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
-        Pubkey::find_program_address(&[ROYALTY_CONFIG_SEED, mint.as_ref()], program_id)
+    // Take an NFT that isn't currently rented back out of the rental vault
+    pub fn delist_rental(ctx: Context<DelistRental>) -> Result<()> {
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let seeds = &[
+            RENTAL_SEED,
+            nft_mint.as_ref(),
+            &[ctx.accounts.rental.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.rental_vault.to_account_info(),
+                    to: ctx.accounts.owner_nft_account.to_account_info(),
+                    authority: ctx.accounts.rental.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        token::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.rental_vault.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.rental.to_account_info(),
+                },
+                signer,
+            ),
+        )?;
+
+        Ok(())
     }
 }
 
@@ -686,11 +2386,45 @@ pub struct Exhibit<'info> {
     pub exhibitor_ft_receiving_account:Account<'info, TokenAccount>,
     #[account(zero)]
     pub escrow_account: Box<Account<'info, Auction>>,
+    // Optional: track the winner gets playback access to, only its authority can link it
+    pub music_track: Option<Account<'info, MusicTrack>>,
     pub clock: Sysvar<'info, Clock>,
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub token_program: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(initial_price: u64, auction_duration_sec: u64)]
+pub struct ExhibitInPlace<'info> {
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(signer)]
+    pub exhibitor: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = exhibitor_nft_token_account.amount == 1,
+        constraint = exhibitor_nft_token_account.owner == exhibitor.key() @ ErrorCode::InvalidNFTOwnership,
+        constraint = exhibitor_nft_token_account.mint == nft_mint.key() @ ErrorCode::TokenMintMismatch
+    )]
+    pub exhibitor_nft_token_account: Account<'info, TokenAccount>,
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Master edition of the NFT, validated by the token metadata program
+    pub nft_edition: AccountInfo<'info>,
+    pub exhibitor_ft_receiving_account: Account<'info, TokenAccount>,
+    #[account(zero)]
+    pub escrow_account: Box<Account<'info, Auction>>,
+    // Optional: track the winner gets playback access to, only its authority can link it
+    pub music_track: Option<Account<'info, MusicTrack>>,
+    pub clock: Sysvar<'info, Clock>,
+    /// CHECK: Checked against the escrow PDA in the instruction
+    #[account(mut)]
+    pub pda: AccountInfo<'info>,
+    /// CHECK: This is the Metaplex Token Metadata program
+    #[account(address = Pubkey::from_str(METADATA_PROGRAM_ID).unwrap() @ ErrorCode::InvalidMetadataProgram)]
+    pub metadata_program: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub token_program: AccountInfo<'info>,
+}
+
 #[derive(Accounts)]
 pub struct Cancel<'info> {
     /// CHECK: This is not dangerous because we don't read or write from this account
@@ -705,10 +2439,18 @@ pub struct Cancel<'info> {
         constraint = escrow_account.exhibitor_pubkey == exhibitor.key(),
         constraint = escrow_account.highest_bidder_pubkey == exhibitor.key(),
         constraint = escrow_account.exhibiting_nft_temp_pubkey == exhibitor_nft_temp_account.key(),
+        constraint = escrow_account.pending_candle_refunds() == 0 @ ErrorCode::CandleRefundsPending,
         close = exhibitor
     )]
     pub escrow_account: Box<Account<'info, Auction>>,
+    // Required for in-place listings to thaw the seller's token account
+    pub nft_mint: Option<Account<'info, Mint>>,
+    /// CHECK: Master edition of the NFT, validated by the token metadata program
+    pub nft_edition: Option<AccountInfo<'info>>,
+    /// CHECK: This is the Metaplex Token Metadata program, checked before thawing
+    pub metadata_program: Option<AccountInfo<'info>>,
     /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
     pub pda: AccountInfo<'info>,
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub token_program: AccountInfo<'info>,
@@ -755,7 +2497,7 @@ pub struct Bid<'info> {
 
 #[derive(Accounts)]
 pub struct Close<'info> {
-    #[account(signer)]
+    #[account(mut, signer)]
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub winning_bidder: AccountInfo<'info>,
     #[account(mut)]
@@ -777,10 +2519,79 @@ pub struct Close<'info> {
         constraint = escrow_account.highest_bidder_pubkey == winning_bidder.key(),
         constraint = escrow_account.highest_bidder_ft_temp_pubkey == highest_bidder_ft_temp_account.key(),
         constraint = escrow_account.end_at <= clock.unix_timestamp,
+        constraint = !escrow_account.is_candle || escrow_account.candle_settled @ ErrorCode::CandleAuctionNotSettled,
+        constraint = escrow_account.pending_candle_refunds() == 0 @ ErrorCode::CandleRefundsPending,
         close = exhibitor
     )]
     pub escrow_account: Box<Account<'info, Auction>>,
+    // Required for in-place listings to thaw the seller's token account
+    pub nft_mint: Option<Account<'info, Mint>>,
+    /// CHECK: Master edition of the NFT, validated by the token metadata program
+    pub nft_edition: Option<AccountInfo<'info>>,
+    /// CHECK: This is the Metaplex Token Metadata program, checked before thawing
+    pub metadata_program: Option<AccountInfo<'info>>,
+    pub clock: Sysvar<'info, Clock>,
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub pda: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseWithTrack<'info> {
+    pub close: Close<'info>,
+    // Track linked at exhibit time
+    #[account(
+        mut,
+        address = close.escrow_account.music_track @ ErrorCode::InvalidMusicTrack
+    )]
+    pub music_track: Account<'info, MusicTrack>,
+    /// CHECK: Token Metadata account of the auctioned NFT, verified in the instruction
+    pub nft_metadata: AccountInfo<'info>,
+    // Authorization cache stamped for the winner
+    #[account(
+        init_if_needed,
+        payer = close.winning_bidder,
+        space = get_user_auth_cache_size(music_track.track_id.len()),
+        seeds = [USER_AUTH_SEED, close.winning_bidder.key().as_ref(), music_track.key().as_ref()],
+        bump
+    )]
+    pub winner_auth: Account<'info, UserAuthCache>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleCandle<'info> {
+    #[account(
+        mut,
+        constraint = escrow_account.is_candle @ ErrorCode::NotCandleAuction,
+        constraint = !escrow_account.candle_settled @ ErrorCode::CandleAuctionAlreadySettled,
+        constraint = escrow_account.end_at <= clock.unix_timestamp @ ErrorCode::AuctionNotEnded
+    )]
+    pub escrow_account: Box<Account<'info, Auction>>,
+    /// CHECK: Address is checked against the SlotHashes sysvar id
+    #[account(address = slot_hashes::ID @ ErrorCode::InvalidSlotHashes)]
+    pub slot_hashes: AccountInfo<'info>,
     pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(bid_index: u8)]
+pub struct RefundCandleBid<'info> {
+    /// CHECK: Compared against the bidder recorded in the auction, receives the temp account rent
+    #[account(mut)]
+    pub bidder: AccountInfo<'info>,
+    #[account(mut)]
+    pub bidder_ft_temp_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub bidder_ft_returning_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = escrow_account.candle_settled @ ErrorCode::CandleAuctionNotSettled,
+        constraint = bid_index < escrow_account.candle_bid_count @ ErrorCode::InvalidCandleBid
+    )]
+    pub escrow_account: Box<Account<'info, Auction>>,
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub pda: AccountInfo<'info>,
     /// CHECK: This is not dangerous because we don't read or write from this account
@@ -810,9 +2621,46 @@ impl<'info> Exhibit<'info> {
 }
 
 
-impl<'info> Cancel<'info> {
-    fn to_transfer_to_exhibitor_context(
-        &self,
+impl<'info> ExhibitInPlace<'info> {
+    fn to_approve_context(&self) -> CpiContext<'_, '_, '_, 'info, Approve<'info>> {
+        let cpi_accounts = Approve {
+            to: self.exhibitor_nft_token_account.to_account_info().clone(),
+            delegate: self.pda.clone(),
+            authority: self.exhibitor.clone(),
+        };
+        CpiContext::new(self.token_program.clone(), cpi_accounts)
+    }
+}
+
+impl<'info> Cancel<'info> {
+    fn thaw_in_place_nft(&self, signers_seeds: &[&[&[u8]]]) -> Result<()> {
+        let nft_mint = self.nft_mint.as_ref().ok_or(ErrorCode::MissingExpectedAccount)?;
+        require!(
+            nft_mint.key() == self.exhibitor_nft_temp_account.mint,
+            ErrorCode::TokenMintMismatch
+        );
+        toggle_delegated_freeze(
+            THAW_DELEGATED_ACCOUNT_INSTRUCTION,
+            self.metadata_program.as_ref().ok_or(ErrorCode::MissingExpectedAccount)?,
+            &self.pda,
+            &self.exhibitor_nft_temp_account.to_account_info(),
+            self.nft_edition.as_ref().ok_or(ErrorCode::MissingExpectedAccount)?,
+            &nft_mint.to_account_info(),
+            &self.token_program,
+            signers_seeds,
+        )
+    }
+
+    fn to_revoke_context(&self) -> CpiContext<'_, '_, '_, 'info, Revoke<'info>> {
+        let cpi_accounts = Revoke {
+            source: self.exhibitor_nft_temp_account.to_account_info().clone(),
+            authority: self.exhibitor.clone(),
+        };
+        CpiContext::new(self.token_program.clone(), cpi_accounts)
+    }
+
+    fn to_transfer_to_exhibitor_context(
+        &self,
     ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
         let cpi_accounts = Transfer {
             from: self.exhibitor_nft_temp_account.to_account_info().clone(),
@@ -836,7 +2684,6 @@ impl<'info> Cancel<'info> {
 }
 
 impl<'info> Bid<'info> {
-
     fn to_set_authority_context(&self) -> CpiContext<'_, '_, '_, 'info, SetAuthority<'info>> {
         let cpi_accounts = SetAuthority {
             account_or_mint: self.bidder_ft_temp_account.to_account_info().clone(),
@@ -880,6 +2727,61 @@ impl<'info> Bid<'info> {
 }
 
 impl<'info> Close<'info> {
+    // Deliver the NFT to the winner and the winning bid to the exhibitor
+    fn settle(&mut self, program_id: &Pubkey) -> Result<()> {
+        let (_, bump_seed) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], program_id);
+        let signers_seeds: &[&[&[u8]]] = &[&[ESCROW_PDA_SEED, &[bump_seed]]];
+
+        // In-place listings are frozen in the seller's account, the PDA moves them as delegate
+        if self.escrow_account.is_in_place {
+            self.thaw_in_place_nft(signers_seeds)?;
+        }
+
+        token::transfer(
+            self.to_transfer_to_highest_bidder_context()
+                .with_signer(signers_seeds),
+            self.exhibitor_nft_temp_account.amount,
+        )?;
+
+        token::transfer(
+            self.to_transfer_to_exhibitor_context()
+                .with_signer(signers_seeds),
+            self.highest_bidder_ft_temp_account.amount,
+        )?;
+
+        token::close_account(
+            self.to_close_ft_context()
+                .with_signer(signers_seeds),
+        )?;
+
+        if !self.escrow_account.is_in_place {
+            token::close_account(
+                self.to_close_nft_context()
+                    .with_signer(signers_seeds),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn thaw_in_place_nft(&self, signers_seeds: &[&[&[u8]]]) -> Result<()> {
+        let nft_mint = self.nft_mint.as_ref().ok_or(ErrorCode::MissingExpectedAccount)?;
+        require!(
+            nft_mint.key() == self.exhibitor_nft_temp_account.mint,
+            ErrorCode::TokenMintMismatch
+        );
+        toggle_delegated_freeze(
+            THAW_DELEGATED_ACCOUNT_INSTRUCTION,
+            self.metadata_program.as_ref().ok_or(ErrorCode::MissingExpectedAccount)?,
+            &self.pda,
+            &self.exhibitor_nft_temp_account.to_account_info(),
+            self.nft_edition.as_ref().ok_or(ErrorCode::MissingExpectedAccount)?,
+            &nft_mint.to_account_info(),
+            &self.token_program,
+            signers_seeds,
+        )
+    }
+
     fn to_transfer_to_exhibitor_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
         let cpi_accounts = Transfer {
             from: self.highest_bidder_ft_temp_account.to_account_info().clone(),
@@ -923,6 +2825,29 @@ impl<'info> Close<'info> {
     }
 }
 
+impl<'info> RefundCandleBid<'info> {
+    fn to_transfer_to_bidder_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.bidder_ft_temp_account.to_account_info().clone(),
+            to: self
+                .bidder_ft_returning_account
+                .to_account_info()
+                .clone(),
+            authority: self.pda.clone(),
+        };
+        CpiContext::new(self.token_program.clone(), cpi_accounts)
+    }
+
+    fn to_close_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.bidder_ft_temp_account.to_account_info().clone(),
+            destination: self.bidder.clone(),
+            authority: self.pda.clone(),
+        };
+        CpiContext::new(self.token_program.clone(), cpi_accounts)
+    }
+}
+
 /// see https://github.com/yoshidan/solana-auction/blob/main/program/src/state.rs#L10
 #[account]
 pub struct Auction {
@@ -934,292 +2859,2876 @@ pub struct Auction {
     pub highest_bidder_pubkey: Pubkey,
     pub highest_bidder_ft_temp_pubkey: Pubkey,
     pub highest_bidder_ft_returning_pubkey: Pubkey,
+    pub is_candle: bool,                     // Candle auction with a retroactive cutoff
+    pub candle_start_at: i64,                // Start of the window the cutoff is picked from
+    pub candle_end_at: i64,                  // Cutoff picked at settlement
+    pub candle_settled: bool,                // Whether the cutoff has been picked
+    pub candle_bid_count: u8,                // Number of entries used in `candle_bids`
+    pub candle_bids: [CandleBid; 10],        // Bids that may still win, see MAX_CANDLE_BIDS
+    pub candle_settle_slot: u64,             // Slot whose hash picks the cutoff
+    pub music_track: Pubkey,                 // Track the winner gets access to (default if none)
+    pub is_in_place: bool,                   // NFT is frozen in the seller's account instead of escrowed
 }
 
-// Add new account structures for music tracks and authorization
-
-#[account]
-pub struct MusicTrack {
-    pub authority: Pubkey,           // Creator/owner of the track
-    pub track_id: String,            // Unique identifier for the track
-    pub track_uri: String,           // IPFS/Arweave URI to the music file
-    pub preview_uri: String,         // URI to the preview version (free)
-    pub metadata_uri: String,        // URI to track metadata
-    pub authorized_collections: Vec<Pubkey>, // List of authorized collection mints
-    pub is_public: bool,             // If true, anyone can play (no NFT needed)
-}
-
-#[account]
-pub struct AuthorizationRegistry {
-    pub authority: Pubkey,           // Admin who can update global settings
-    pub track_count: u64,            // Total number of tracks registered
-    pub bump: u8,                    // PDA bump
+impl Auction {
+    // Candle bids that still hold escrowed funds and are not the winning bid
+    pub fn pending_candle_refunds(&self) -> usize {
+        self.candle_bids[..self.candle_bid_count as usize]
+            .iter()
+            .filter(|candle_bid| {
+                !candle_bid.refunded
+                    && candle_bid.bidder_ft_temp_pubkey != self.highest_bidder_ft_temp_pubkey
+            })
+            .count()
+    }
 }
 
-// Add user authorization cache to improve efficiency
-#[account]
-pub struct UserAuthCache {
-    pub user: Pubkey,                // User who is authorized
-    pub track_id: String,            // Track they're authorized to play
-    pub expires_at: i64,             // When the authorization expires (unix timestamp)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct CandleBid {
+    pub bidder_pubkey: Pubkey,
+    pub bidder_ft_temp_pubkey: Pubkey,
+    pub bidder_ft_returning_pubkey: Pubkey,
+    pub price: u64,
+    pub placed_at: i64,                      // Unix timestamp the bid landed at
+    pub refunded: bool,
 }
 
-// Add new account validation structures
+// Uniform-price auction for multiple editions of the same release
 
 #[derive(Accounts)]
-#[instruction(track_id: String)]
-pub struct RegisterMusicTrack<'info> {
+#[instruction(auction_id: u64)]
+pub struct CreateEditionAuction<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
-    
+    pub seller: Signer<'info>,
+
+    pub edition_mint: Account<'info, Mint>,
+
+    pub payment_mint: Account<'info, Mint>,
+
     #[account(
-        init,
-        payer = authority,
-        space = 8 + 32 + 4 + track_id.len() + 4 + 100 + 4 + 100 + 4 + 100 + 4 + (32 * 20) + 1, // Approximate space
-        seeds = [MUSIC_TRACK_SEED, track_id.as_bytes()],
-        bump
+        constraint = seller_payment_account.mint == payment_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = seller_payment_account.owner == seller.key() @ ErrorCode::InvalidTokenAccount
     )]
-    pub music_track: Account<'info, MusicTrack>,
-    
+    pub seller_payment_account: Account<'info, TokenAccount>,
+
     #[account(
-        init_if_needed,
-        payer = authority,
-        space = 8 + 32 + 8 + 1,
-        seeds = [AUTH_REGISTRY_SEED],
+        init,
+        payer = seller,
+        space = get_edition_auction_size(MAX_EDITION_PRICE_LEVELS),
+        seeds = [EDITION_AUCTION_SEED, seller.key().as_ref(), edition_mint.key().as_ref(), auction_id.to_le_bytes().as_ref()],
         bump
     )]
-    pub auth_registry: Account<'info, AuthorizationRegistry>,
-    
-    pub system_program: Program<'info, System>,
-}
+    pub edition_auction: Box<Account<'info, EditionAuction>>,
 
-#[derive(Accounts)]
-pub struct UpdateTrackAuthorization<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
     #[account(
-        mut,
-        constraint = music_track.authority == authority.key() @ ErrorCode::UnauthorizedPlayback
+        init,
+        payer = seller,
+        token::mint = edition_mint,
+        token::authority = edition_auction,
+        seeds = [EDITION_VAULT_SEED, edition_auction.key().as_ref()],
+        bump
     )]
-    pub music_track: Account<'info, MusicTrack>,
-}
+    pub edition_vault: Account<'info, TokenAccount>,
 
-#[derive(Accounts)]
-pub struct CreateUserAuthCache<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
-    
-    pub music_track: Account<'info, MusicTrack>,
-    
     #[account(
         init,
-        payer = user,
-        space = 8 + 32 + 4 + music_track.track_id.len() + 8,
-        seeds = [USER_AUTH_SEED, user.key().as_ref(), music_track.track_id.as_bytes()],
+        payer = seller,
+        token::mint = payment_mint,
+        token::authority = edition_auction,
+        seeds = [EDITION_PAYMENT_VAULT_SEED, edition_auction.key().as_ref()],
         bump
     )]
-    pub user_auth: Account<'info, UserAuthCache>,
-    
+    pub payment_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct VerifyPlaybackAuthorization<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
-    
-    pub music_track: Account<'info, MusicTrack>,
-    
-    // Optional: User's token account for the NFT
-    pub user_token_account: Option<Account<'info, TokenAccount>>,
-    
-    // Optional: NFT metadata account
-    /// CHECK: Verified in the instruction
-    pub nft_metadata: Option<AccountInfo<'info>>,
-    
-    /// CHECK: This is the Metaplex Token Metadata program
-    pub metadata_program: AccountInfo<'info>,
-    
-    // Optional: User authorization cache
+pub struct CreateEscrowedEditionAuction<'info> {
+    pub create: CreateEditionAuction<'info>,
+
     #[account(
         mut,
-        seeds = [USER_AUTH_SEED, user.key().as_ref(), music_track.track_id.as_bytes()],
-        bump,
-        constraint = user_auth.user == user.key() && user_auth.track_id == music_track.track_id,
-        constraint = Clock::get().unwrap().unix_timestamp <= user_auth.expires_at @ ErrorCode::AuthorizationExpired,
-        required = false
+        constraint = seller_edition_account.mint == create.edition_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = seller_edition_account.owner == create.seller.key() @ ErrorCode::InvalidTokenAccount
     )]
-    pub user_auth: Option<Account<'info, UserAuthCache>>,
-    
-    pub system_program: Program<'info, System>,
-    
-    pub clock: Sysvar<'info, Clock>,
-}
-
-// Define royalty-related account structures
-
-#[account]
-pub struct RoyaltyConfig {
-    pub mint: Pubkey,                         // NFT mint this config belongs to
-    pub total_basis_points: u16,              // Total royalty (e.g., 1000 = 10%)
-    pub recipients: Vec<RoyaltyRecipient>,    // List of recipients
-    pub authority: Pubkey,                    // Who can modify this config
-    pub is_mutable: bool,                     // Whether config can be changed
-    pub bump: u8,                             // PDA bump seed for easier CPI
+    pub seller_edition_account: Account<'info, TokenAccount>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct RoyaltyRecipient {
-    pub recipient: Pubkey,                    // Recipient wallet
-    pub basis_points: u16,                    // Share (e.g., 500 = 5%)
-    pub recipient_type: RecipientType,        // Artist, platform, collaborator, etc.
-}
+impl<'info> CreateEditionAuction<'info> {
+    fn init_edition_auction(
+        &mut self,
+        auction_id: u64,
+        edition_count: u32,
+        reserve_price: u64,
+        auction_duration_sec: u64,
+        is_minted: bool,
+        bump: u8,
+    ) -> Result<()> {
+        require!(edition_count > 0, ErrorCode::InvalidEditionAuction);
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-pub enum RecipientType {
-    Artist,
-    Platform,
-    Collaborator,
-    Other,
+        let edition_auction = &mut self.edition_auction;
+        edition_auction.auction_id = auction_id;
+        edition_auction.seller = self.seller.key();
+        edition_auction.edition_mint = self.edition_mint.key();
+        edition_auction.payment_mint = self.payment_mint.key();
+        edition_auction.seller_payment_account = self.seller_payment_account.key();
+        edition_auction.edition_count = edition_count;
+        edition_auction.reserve_price = reserve_price;
+        edition_auction.end_at = Clock::get()?.unix_timestamp + auction_duration_sec as i64;
+        edition_auction.is_minted = is_minted;
+        edition_auction.price_levels = Vec::new();
+        edition_auction.bump = bump;
+        Ok(())
+    }
 }
 
-// Account validation structures for royalty operations
-
 #[derive(Accounts)]
-pub struct CreateRoyaltyConfig<'info> {
+#[instruction(bid_index: u16)]
+pub struct PlaceEditionBid<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    pub nft_mint: Account<'info, Mint>,
-    
-    // Verify authority is connected to the NFT by requiring their token account
+    pub bidder: Signer<'info>,
+
     #[account(
-        constraint = nft_token_account.mint == nft_mint.key(),
-        constraint = nft_token_account.owner == authority.key(),
-        constraint = nft_token_account.amount > 0
+        mut,
+        constraint = edition_auction.end_at > Clock::get()?.unix_timestamp @ ErrorCode::EditionAuctionEnded
     )]
-    pub nft_token_account: Account<'info, TokenAccount>,
-    
+    pub edition_auction: Box<Account<'info, EditionAuction>>,
+
     #[account(
         init,
-        payer = authority,
-        space = get_royalty_config_size(recipients.len()),
-        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
+        payer = bidder,
+        space = 8 + 32 + 32 + 4 + 8 + 8 + 2 + 1,
+        seeds = [EDITION_BID_SEED, edition_auction.key().as_ref(), bidder.key().as_ref(), bid_index.to_le_bytes().as_ref()],
         bump
     )]
-    pub royalty_config: Account<'info, RoyaltyConfig>,
-    
-    pub system_program: Program<'info, System>,
-}
+    pub edition_bid: Account<'info, EditionBid>,
 
-#[derive(Accounts)]
-pub struct UpdateRoyaltyConfig<'info> {
     #[account(
-        constraint = authority.key() == royalty_config.authority @ ErrorCode::UnauthorizedRoyaltyUpdate
+        mut,
+        constraint = bidder_payment_account.mint == edition_auction.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = bidder_payment_account.owner == bidder.key() @ ErrorCode::InvalidTokenAccount
     )]
-    pub authority: Signer<'info>,
-    
-    pub nft_mint: Account<'info, Mint>,
-    
-    // Add optional NFT ownership verification
-    // This allows either the original authority OR the current NFT owner to update royalties
-    // if the config is mutable
+    pub bidder_payment_account: Account<'info, TokenAccount>,
+
     #[account(
-        constraint = nft_token_account.mint == nft_mint.key(),
-        constraint = nft_token_account.owner == authority.key(),
-        constraint = nft_token_account.amount > 0,
-        required = false
+        mut,
+        seeds = [EDITION_PAYMENT_VAULT_SEED, edition_auction.key().as_ref()],
+        bump
     )]
-    pub nft_token_account: Option<Account<'info, TokenAccount>>,
-    
+    pub payment_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClearEditionAuction<'info> {
     #[account(
         mut,
-        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
-        bump = royalty_config.bump,
-        constraint = royalty_config.mint == nft_mint.key() @ ErrorCode::InvalidRoyaltyConfig
+        constraint = edition_auction.end_at <= Clock::get()?.unix_timestamp @ ErrorCode::AuctionNotEnded,
+        constraint = !edition_auction.is_cleared @ ErrorCode::EditionAuctionAlreadyCleared
     )]
-    pub royalty_config: Account<'info, RoyaltyConfig>,
-    
-    pub system_program: Program<'info, System>,
+    pub edition_auction: Box<Account<'info, EditionAuction>>,
 }
 
 #[derive(Accounts)]
-pub struct ProcessSale<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    
-    /// CHECK: This is the seller receiving payment
+pub struct ClaimEditionBid<'info> {
+    /// CHECK: Bidder the claim is for, receives the editions, the refund and the bid's rent
+    #[account(mut, address = edition_bid.bidder @ ErrorCode::InvalidEditionAuction)]
+    pub bidder: AccountInfo<'info>,
+
+    // Funds the bidder's edition account when it doesn't exist yet
     #[account(mut)]
-    pub seller: AccountInfo<'info>,
-    
-    pub nft_mint: Account<'info, Mint>,
-    
-    // Add NFT token account owned by the seller to verify they own the NFT
+    pub payer: Signer<'info>,
+
     #[account(
-        constraint = seller_nft_account.mint == nft_mint.key(),
-        constraint = seller_nft_account.owner == seller.key(),
-        constraint = seller_nft_account.amount == 1
+        mut,
+        constraint = edition_auction.is_cleared @ ErrorCode::EditionAuctionNotCleared
     )]
-    pub seller_nft_account: Account<'info, TokenAccount>,
-    
+    pub edition_auction: Box<Account<'info, EditionAuction>>,
+
     #[account(
-        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
+        mut,
+        seeds = [EDITION_BID_SEED, edition_auction.key().as_ref(), bidder.key().as_ref(), edition_bid.bid_index.to_le_bytes().as_ref()],
+        bump = edition_bid.bump,
+        close = bidder
+    )]
+    pub edition_bid: Account<'info, EditionBid>,
+
+    #[account(
+        mut,
+        address = edition_auction.edition_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub edition_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = edition_mint,
+        associated_token::authority = bidder
+    )]
+    pub bidder_edition_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = bidder_payment_account.mint == edition_auction.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = bidder_payment_account.owner == bidder.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub bidder_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [EDITION_VAULT_SEED, edition_auction.key().as_ref()],
+        bump
+    )]
+    pub edition_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [EDITION_PAYMENT_VAULT_SEED, edition_auction.key().as_ref()],
+        bump
+    )]
+    pub payment_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawEditionProceeds<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = edition_auction.seller == seller.key() @ ErrorCode::InvalidEditionAuction,
+        constraint = edition_auction.is_cleared @ ErrorCode::EditionAuctionNotCleared,
+        constraint = edition_auction.claimed_count == edition_auction.bid_count @ ErrorCode::EditionClaimsPending,
+        close = seller
+    )]
+    pub edition_auction: Box<Account<'info, EditionAuction>>,
+
+    #[account(
+        mut,
+        address = edition_auction.seller_payment_account @ ErrorCode::InvalidTokenAccount
+    )]
+    pub seller_payment_account: Account<'info, TokenAccount>,
+
+    // Receives unsold editions when they were escrowed
+    #[account(
+        mut,
+        constraint = seller_edition_account.mint == edition_auction.edition_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub seller_edition_account: Account<'info, TokenAccount>,
+
+    // Mint authority goes back to the seller when editions were minted on claim
+    #[account(
+        mut,
+        address = edition_auction.edition_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub edition_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [EDITION_VAULT_SEED, edition_auction.key().as_ref()],
+        bump
+    )]
+    pub edition_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [EDITION_PAYMENT_VAULT_SEED, edition_auction.key().as_ref()],
+        bump
+    )]
+    pub payment_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+pub struct EditionAuction {
+    pub auction_id: u64,                      // Tells auctions of the same mint apart
+    pub seller: Pubkey,                       // Receives the proceeds
+    pub edition_mint: Pubkey,                 // Mint of the editions on offer
+    pub payment_mint: Pubkey,                 // Token bids are paid in
+    pub seller_payment_account: Pubkey,       // Seller token account for the proceeds
+    pub edition_count: u32,                   // Number of editions on offer
+    pub reserve_price: u64,                   // Minimum price per edition
+    pub end_at: i64,                          // When bidding closes (unix timestamp)
+    pub is_minted: bool,                      // Mint editions on claim instead of using the vault
+    pub is_cleared: bool,                     // Whether the clearing price is known
+    pub clearing_price: u64,                  // Price every winner pays per edition
+    pub units_sold: u32,                      // Editions allocated to winning bids
+    pub marginal_units: u64,                  // Editions left for bids at the clearing price
+    pub bid_count: u32,                       // Number of bids placed
+    pub claimed_count: u32,                   // Number of bids claimed
+    pub price_levels: Vec<EditionPriceLevel>, // Aggregated demand, highest price first
+    pub bump: u8,                             // PDA bump seed
+}
+
+impl EditionAuction {
+    // Editions a bid receives once the auction is cleared. Bids above the clearing price are
+    // filled in full, bids at the clearing price are filled in the order they were placed.
+    pub fn filled_units(&self, edition_bid: &EditionBid) -> u64 {
+        if !self.is_cleared || edition_bid.price < self.clearing_price {
+            return 0;
+        }
+        if edition_bid.price > self.clearing_price {
+            return edition_bid.quantity as u64;
+        }
+        std::cmp::min(
+            edition_bid.quantity as u64,
+            self.marginal_units.saturating_sub(edition_bid.level_offset),
+        )
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EditionPriceLevel {
+    pub price: u64,                           // Price per edition
+    pub quantity: u64,                        // Total editions bid for at this price
+}
+
+#[account]
+pub struct EditionBid {
+    pub auction: Pubkey,                      // Edition auction this bid belongs to
+    pub bidder: Pubkey,                       // Wallet that placed the bid
+    pub quantity: u32,                        // Editions bid for
+    pub price: u64,                           // Price offered per edition
+    pub level_offset: u64,                    // Editions bid at this price before this bid
+    pub bid_index: u16,                       // Tells a bidder's bids on the auction apart
+    pub bump: u8,                             // PDA bump seed
+}
+
+// Add new account structures for music tracks and authorization
+
+#[account]
+pub struct MusicTrack {
+    pub authority: Pubkey,           // Creator/owner of the track
+    pub track_id: String,            // Unique identifier for the track
+    pub track_uri: String,           // IPFS/Arweave URI to the music file
+    pub preview_uri: String,         // URI to the preview version (free)
+    pub metadata_uri: String,        // URI to track metadata
+    pub authorized_collections: Vec<Pubkey>, // List of authorized collection mints
+    pub is_public: bool,             // If true, anyone can play (no NFT needed)
+    pub auth_ttl: i64,               // Seconds a playback authorization stays cached
+    pub token_gates: Vec<TokenGate>, // Fungible tokens that unlock the track above a minimum balance
+    pub access_payment_mint: Pubkey, // Token time passes are sold in, default if not for sale
+    pub royalty_mint: Pubkey,        // NFT mint whose royalty config splits track revenue
+    pub access_tiers: Vec<AccessTier>, // Time passes on sale
+    pub release_at: i64,             // Before this only pre-release collections can play (0 if released)
+    pub holders_only_until: i64,     // After this the track is public (0 to stay gated)
+    pub prerelease_collections: Vec<Pubkey>, // Collections or mints let in before the release
+    pub collection_tiers: Vec<CollectionTier>, // Authorized collections granting more than the base tier
+    pub tier_uris: Vec<String>,      // URI or asset id per tier above the base, the base tier plays track_uri
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CollectionTier {
+    pub collection: Pubkey,          // Authorized collection or mint
+    pub tier: u8,                    // Access tier its holders get
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AccessTier {
+    pub price: u64,                  // Price in the track's access payment mint
+    pub duration_sec: i64,           // Access granted per purchase
+}
+
+#[account]
+pub struct AccessPass {
+    pub user: Pubkey,                // Listener who bought the pass
+    pub music_track: Pubkey,         // Track the pass unlocks
+    pub expires_at: i64,             // When access ends (unix timestamp)
+    pub bump: u8,                    // PDA bump
+}
+
+#[account]
+pub struct ListeningDelegation {
+    pub holder: Pubkey,              // Wallet holding the token
+    pub delegate: Pubkey,            // Wallet allowed to play with it
+    pub mint: Pubkey,                // Token being shared
+    pub music_track: Pubkey,         // Track in scope, default for every track
+    pub expires_at: i64,             // When the delegation ends (unix timestamp)
+    pub bump: u8,                    // PDA bump
+}
+
+#[account]
+pub struct SessionKey {
+    pub user: Pubkey,                // Wallet the session acts for
+    pub session_key: Pubkey,         // Ephemeral key signing playback verification
+    pub music_tracks: Vec<Pubkey>,   // Tracks in scope
+    pub artists: Vec<Pubkey>,        // Artists whose tracks are all in scope
+    pub expires_at: i64,             // When the session ends (unix timestamp)
+    pub lamport_budget: u64,         // Lamports given to the key for fees
+    pub bump: u8,                    // PDA bump
+}
+
+impl SessionKey {
+    // Whether the session covers a track, an empty scope covers every track
+    pub fn covers(&self, music_track: &Pubkey, artist: &Pubkey) -> bool {
+        (self.music_tracks.is_empty() && self.artists.is_empty())
+            || self.music_tracks.contains(music_track)
+            || self.artists.contains(artist)
+    }
+}
+
+#[account]
+pub struct PlayEpoch {
+    pub epoch: u64,                  // Unix time divided by PLAY_EPOCH_SEC
+    pub payment_mint: Pubkey,        // Token the pool pays out in
+    pub total_plays: u64,            // Plays counted across all tracks
+    pub funded_amount: u64,          // Revenue paid into the pool
+    pub claimed_plays: u64,          // Plays of tracks that have claimed their share
+    pub claimed_amount: u64,         // Revenue paid out to those tracks
+    pub bump: u8,                    // PDA bump
+}
+
+#[account]
+pub struct TrackPlays {
+    pub music_track: Pubkey,         // Track the plays were counted for
+    pub epoch: u64,                  // Epoch the plays were counted in
+    pub plays: u64,                  // Plays counted for the track
+    pub claimed: bool,               // Whether the track's share was paid out
+    pub bump: u8,                    // PDA bump
+}
+
+#[account]
+pub struct SubscriptionPlan {
+    pub artist: Pubkey,              // Track authority whose catalogue the plan covers
+    pub payment_mint: Pubkey,        // Token the subscription is paid in
+    pub artist_payment_account: Pubkey, // Artist token account receiving payments
+    pub price: u64,                  // Price per period
+    pub period_sec: i64,             // Length of one period
+    pub bump: u8,                    // PDA bump
+}
+
+#[account]
+pub struct Subscription {
+    pub subscriber: Pubkey,          // Listener who subscribed
+    pub artist: Pubkey,              // Artist of the plan, matched against MusicTrack.authority
+    pub subscription_plan: Pubkey,   // Plan the subscription was bought from
+    pub expires_at: i64,             // When the paid period ends (unix timestamp)
+    pub bump: u8,                    // PDA bump
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TokenGate {
+    pub mint: Pubkey,                // Fan token mint
+    pub min_amount: u64,             // Minimum balance, in base units, needed to play
+}
+
+impl MusicTrack {
+    // Whether the track is out at `now`
+    pub fn is_released(&self, now: i64) -> bool {
+        now >= self.release_at
+    }
+    
+    // Public tracks, and gated tracks past their holders-only window, need no token
+    pub fn is_public_at(&self, now: i64) -> bool {
+        self.is_released(now)
+            && (self.is_public || (self.holders_only_until != 0 && now >= self.holders_only_until))
+    }
+    
+    // Access tier granted to holders of an authorized collection or mint
+    pub fn tier_for(&self, collection_mint: &Pubkey) -> u8 {
+        self.collection_tiers
+            .iter()
+            .find(|entry| entry.collection == *collection_mint)
+            .map_or(BASE_ACCESS_TIER, |entry| entry.tier)
+    }
+    
+    // Collections or mints that unlock the track at `now`
+    pub fn unlocking_collections(&self, now: i64) -> &Vec<Pubkey> {
+        if self.is_released(now) {
+            &self.authorized_collections
+        } else {
+            &self.prerelease_collections
+        }
+    }
+    
+    // Authorize a collection or mint, ignoring ones that are already present
+    pub fn authorize_collection(&mut self, collection_mint: Pubkey) -> Result<()> {
+        if !self.authorized_collections.contains(&collection_mint) {
+            // Limit the number of collections to prevent excessive storage costs
+            require!(
+                self.authorized_collections.len() < MAX_AUTHORIZED_COLLECTIONS,
+                ErrorCode::TooManyCollections
+            );
+            self.authorized_collections.push(collection_mint);
+        }
+        Ok(())
+    }
+}
+
+#[account]
+pub struct AuthorizationRegistry {
+    pub authority: Pubkey,           // Admin who can update global settings
+    pub track_count: u64,            // Total number of tracks registered
+    pub default_auth_ttl: i64,       // Authorization TTL given to new tracks
+    pub play_attestor: Pubkey,       // Allowed to attest plays counted off-chain
+    pub bump: u8,                    // PDA bump
+}
+
+// Add user authorization cache to improve efficiency
+#[account]
+pub struct UserAuthCache {
+    pub version: u8,                 // Layout version, see USER_AUTH_CACHE_VERSION
+    pub user: Pubkey,                // User who is authorized
+    pub track_id: String,            // Track they're authorized to play
+    pub expires_at: i64,             // When the authorization expires (unix timestamp)
+    pub granted_via: Pubkey,         // Collection or mint that granted access, default for public tracks
+    pub payer: Pubkey,               // Who paid the rent and gets it back on close
+    pub created_at: i64,             // When the cache was created (unix timestamp)
+    pub tier: u8,                    // Access tier granted
+}
+
+impl UserAuthCache {
+    // Whether whatever granted this authorization still unlocks the track at `now`
+    pub fn is_still_granted(&self, music_track: &MusicTrack, now: i64) -> bool {
+        if self.granted_via == Pubkey::default() {
+            music_track.is_public_at(now)
+        } else {
+            music_track.unlocking_collections(now).contains(&self.granted_via)
+                || (music_track.is_released(now)
+                    && music_track.token_gates.iter().any(|gate| gate.mint == self.granted_via))
+        }
+    }
+}
+
+// MusicTrack as registered under the old, unscoped seeds, read only to migrate it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyMusicTrack {
+    pub authority: Pubkey,           // Creator/owner of the track
+    pub track_id: String,            // Unique identifier for the track
+    pub track_uri: String,           // IPFS/Arweave URI to the music file
+    pub preview_uri: String,         // URI to the preview version (free)
+    pub metadata_uri: String,        // URI to track metadata
+    pub authorized_collections: Vec<Pubkey>, // List of authorized collection mints
+    pub is_public: bool,             // If true, anyone can play (no NFT needed)
+}
+
+impl LegacyMusicTrack {
+    // The track in the current layout, with every field added since at its default
+    pub fn to_music_track(&self) -> MusicTrack {
+        MusicTrack {
+            authority: self.authority,
+            track_id: self.track_id.clone(),
+            track_uri: self.track_uri.clone(),
+            preview_uri: self.preview_uri.clone(),
+            metadata_uri: self.metadata_uri.clone(),
+            authorized_collections: self.authorized_collections.clone(),
+            is_public: self.is_public,
+            auth_ttl: DEFAULT_AUTH_TTL,
+            token_gates: Vec::new(),
+            access_payment_mint: Pubkey::default(),
+            royalty_mint: Pubkey::default(),
+            access_tiers: Vec::new(),
+            release_at: 0,
+            holders_only_until: 0,
+            prerelease_collections: Vec::new(),
+            collection_tiers: Vec::new(),
+            tier_uris: Vec::new(),
+        }
+    }
+}
+
+// AuthorizationRegistry as created before the TTL and attestor settings, read only to migrate it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyAuthorizationRegistry {
+    pub authority: Pubkey,           // Admin who can update global settings
+    pub track_count: u64,            // Total number of tracks registered
+    pub bump: u8,                    // PDA bump
+}
+
+// UserAuthCache as created under the old track id seeds, read only to close it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyUserAuthCache {
+    pub user: Pubkey,                // User who is authorized
+    pub track_id: String,            // Track they're authorized to play
+    pub expires_at: i64,             // When the authorization expires (unix timestamp)
+}
+
+// Legacy accounts keep the discriminator of the account type they were written as
+impl AccountDeserialize for LegacyMusicTrack {
+    fn try_deserialize(buf: &mut &[u8]) -> Result<Self> {
+        deserialize_legacy_account(buf, MusicTrack::discriminator())
+    }
+    
+    fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+        deserialize_legacy_account(buf, MusicTrack::discriminator())
+    }
+}
+
+impl AccountDeserialize for LegacyUserAuthCache {
+    fn try_deserialize(buf: &mut &[u8]) -> Result<Self> {
+        deserialize_legacy_account(buf, UserAuthCache::discriminator())
+    }
+    
+    fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+        deserialize_legacy_account(buf, UserAuthCache::discriminator())
+    }
+}
+
+impl AccountSerialize for LegacyMusicTrack {}
+impl AccountSerialize for LegacyUserAuthCache {}
+
+impl Owner for LegacyMusicTrack {
+    fn owner() -> Pubkey {
+        ID
+    }
+}
+
+impl Owner for LegacyUserAuthCache {
+    fn owner() -> Pubkey {
+        ID
+    }
+}
+
+// Outcome of a playback check, set as return data for clients and CPI callers
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PlaybackAuthorization {
+    pub granted: bool,               // Whether the user may play the track
+    pub tier: u8,                    // Access tier granted, see MusicTrack::tier_uris
+    pub expires_at: i64,             // Until when the grant holds (unix timestamp), 0 when denied
+    pub reason: PlaybackReason,      // What granted or denied access
+}
+
+impl PlaybackAuthorization {
+    fn granted(reason: PlaybackReason, tier: u8, expires_at: i64) -> Self {
+        Self { granted: true, tier, expires_at, reason }
+    }
+    
+    fn denied(reason: PlaybackReason) -> Self {
+        Self { granted: false, tier: BASE_ACCESS_TIER, expires_at: 0, reason }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum PlaybackReason {
+    Denied,                          // Nothing the user presented unlocks the track
+    NotReleased,                     // The track is not out yet and nothing let the user in early
+    Public,                          // The track is public
+    Cache,                           // A still valid authorization cache
+    Nft,                             // The NFT itself is authorized
+    Collection,                      // The NFT is in a verified, authorized collection
+    TokenGate,                       // Enough fan tokens
+    AccessPass,                      // A purchased time pass
+    Subscription,                    // A subscription to the artist
+}
+
+// Add new account validation structures
+
+#[derive(Accounts)]
+#[instruction(track_id: String, track_uri: String, preview_uri: String, metadata_uri: String)]
+pub struct RegisterMusicTrack<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    // Sized for the URIs it's registered with, update_music_track grows it for longer ones
+    #[account(
+        init,
+        payer = authority,
+        space = get_music_track_size_for(&track_id, [&track_uri, &preview_uri, &metadata_uri], &[]),
+        seeds = [MUSIC_TRACK_SEED, authority.key().as_ref(), track_id.as_bytes()],
+        bump
+    )]
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = get_auth_registry_size(),
+        seeds = [AUTH_REGISTRY_SEED],
+        bump
+    )]
+    pub auth_registry: Account<'info, AuthorizationRegistry>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(track_id: String)]
+pub struct MigrateMusicTrack<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    // Track registered before ids were scoped to their authority
+    #[account(
+        mut,
+        close = authority,
+        seeds = [MUSIC_TRACK_SEED, track_id.as_bytes()],
+        bump,
+        constraint = legacy_music_track.authority == authority.key() @ ErrorCode::UnauthorizedPlayback
+    )]
+    pub legacy_music_track: Account<'info, LegacyMusicTrack>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = get_music_track_size(&legacy_music_track.to_music_track()),
+        seeds = [MUSIC_TRACK_SEED, authority.key().as_ref(), track_id.as_bytes()],
+        bump
+    )]
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(
+        seeds = [AUTH_REGISTRY_SEED],
+        bump
+    )]
+    pub auth_registry: Account<'info, AuthorizationRegistry>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateRegistry<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    /// CHECK: Registry in its old layout, read and rewritten in the instruction
+    #[account(
+        mut,
+        seeds = [AUTH_REGISTRY_SEED],
+        bump,
+        owner = ID
+    )]
+    pub auth_registry: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateTrackAuthorization<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = music_track.authority == authority.key() @ ErrorCode::UnauthorizedPlayback
+    )]
+    pub music_track: Account<'info, MusicTrack>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAuthRegistry<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [AUTH_REGISTRY_SEED],
+        bump,
+        constraint = auth_registry.authority == authority.key() @ ErrorCode::UnauthorizedPlayback
+    )]
+    pub auth_registry: Account<'info, AuthorizationRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMusicTrack<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = music_track.authority == authority.key() @ ErrorCode::UnauthorizedPlayback
+    )]
+    pub music_track: Account<'info, MusicTrack>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMusicTrack<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        close = authority,
+        constraint = music_track.authority == authority.key() @ ErrorCode::UnauthorizedPlayback
+    )]
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(
+        mut,
+        seeds = [AUTH_REGISTRY_SEED],
+        bump
+    )]
+    pub auth_registry: Account<'info, AuthorizationRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct PurchaseAccess<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        constraint = music_track.access_payment_mint != Pubkey::default() @ ErrorCode::AccessNotForSale
+    )]
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [ACCESS_PASS_SEED, buyer.key().as_ref(), music_track.key().as_ref()],
+        bump
+    )]
+    pub access_pass: Account<'info, AccessPass>,
+    
+    /// CHECK: Royalty config PDA of the track's royalty mint, it may not exist
+    #[account(
+        seeds = [ROYALTY_CONFIG_SEED, music_track.royalty_mint.as_ref()],
+        bump
+    )]
+    pub royalty_config: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = buyer_payment_account.mint == music_track.access_payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = artist_payment_account.owner == music_track.authority @ ErrorCode::InvalidTokenAccount,
+        constraint = artist_payment_account.mint == music_track.access_payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub artist_payment_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSubscriptionPlan<'info> {
+    #[account(mut)]
+    pub artist: Signer<'info>,
+    
+    pub payment_mint: Account<'info, Mint>,
+    
+    #[account(
+        constraint = artist_payment_account.mint == payment_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = artist_payment_account.owner == artist.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub artist_payment_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = artist,
+        space = 8 + 32 + 32 + 32 + 8 + 8 + 1,
+        seeds = [SUBSCRIPTION_PLAN_SEED, artist.key().as_ref()],
+        bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSubscriptionPlan<'info> {
+    pub artist: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [SUBSCRIPTION_PLAN_SEED, artist.key().as_ref()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+}
+
+#[derive(Accounts)]
+pub struct Subscribe<'info> {
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    
+    #[account(
+        init,
+        payer = subscriber,
+        space = 8 + 32 + 32 + 32 + 8 + 1,
+        seeds = [SUBSCRIPTION_SEED, subscription_plan.key().as_ref(), subscriber.key().as_ref()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    
+    #[account(
+        mut,
+        constraint = subscriber_payment_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = subscriber_payment_account.mint == subscription_plan.payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub subscriber_payment_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        address = subscription_plan.artist_payment_account @ ErrorCode::InvalidSubscriptionPlan
+    )]
+    pub artist_payment_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Subscribe<'info> {
+    fn to_pay_artist_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.subscriber_payment_account.to_account_info(),
+            to: self.artist_payment_account.to_account_info(),
+            authority: self.subscriber.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct RenewSubscription<'info> {
+    pub subscriber: Signer<'info>,
+    
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    
+    #[account(
+        mut,
+        seeds = [SUBSCRIPTION_SEED, subscription_plan.key().as_ref(), subscriber.key().as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    
+    #[account(
+        mut,
+        constraint = subscriber_payment_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = subscriber_payment_account.mint == subscription_plan.payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub subscriber_payment_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        address = subscription_plan.artist_payment_account @ ErrorCode::InvalidSubscriptionPlan
+    )]
+    pub artist_payment_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> RenewSubscription<'info> {
+    fn to_pay_artist_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.subscriber_payment_account.to_account_info(),
+            to: self.artist_payment_account.to_account_info(),
+            authority: self.subscriber.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct CancelSubscription<'info> {
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    
+    #[account(
+        mut,
+        close = subscriber,
+        constraint = subscription.subscriber == subscriber.key() @ ErrorCode::InvalidSubscription
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct DelegateListening<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+    
+    #[account(
+        constraint = holder_token_account.owner == holder.key() @ ErrorCode::InvalidNFTOwnership,
+        constraint = holder_token_account.amount > 0 @ ErrorCode::InvalidNFTOwnership
+    )]
+    pub holder_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = holder,
+        space = 8 + 32 + 32 + 32 + 32 + 8 + 1,
+        seeds = [
+            LISTENING_DELEGATION_SEED,
+            holder.key().as_ref(),
+            holder_token_account.mint.as_ref(),
+            delegate.as_ref()
+        ],
+        bump
+    )]
+    pub delegation: Account<'info, ListeningDelegation>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeListeningDelegation<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+    
+    #[account(
+        mut,
+        close = holder,
+        constraint = delegation.holder == holder.key() @ ErrorCode::InvalidDelegation
+    )]
+    pub delegation: Account<'info, ListeningDelegation>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSessionKey<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: Ephemeral key the session is for, only receives the lamport budget
+    #[account(mut)]
+    pub session_key: AccountInfo<'info>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 32 + 4 + 4 + (32 * MAX_SESSION_SCOPE) + 8 + 8 + 1,
+        seeds = [SESSION_KEY_SEED, user.key().as_ref(), session_key.key().as_ref()],
+        bump
+    )]
+    pub session: Account<'info, SessionKey>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSessionKey<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        close = user,
+        constraint = session.user == user.key() @ ErrorCode::InvalidSessionKey
+    )]
+    pub session: Account<'info, SessionKey>,
+}
+
+#[derive(Accounts)]
+#[instruction(epoch: u64)]
+pub struct CreatePlayEpoch<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [AUTH_REGISTRY_SEED],
+        bump,
+        constraint = auth_registry.authority == authority.key() @ ErrorCode::UnauthorizedPlayback
+    )]
+    pub auth_registry: Account<'info, AuthorizationRegistry>,
+    
+    pub payment_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 8 + 32 + 8 + 8 + 8 + 8 + 1,
+        seeds = [PLAY_EPOCH_SEED, payment_mint.key().as_ref(), epoch.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub play_epoch: Account<'info, PlayEpoch>,
+    
+    #[account(
+        init,
+        payer = authority,
+        token::mint = payment_mint,
+        token::authority = play_epoch,
+        seeds = [PLAY_EPOCH_VAULT_SEED, play_epoch.key().as_ref()],
+        bump
+    )]
+    pub play_epoch_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct FundPlayEpoch<'info> {
+    pub funder: Signer<'info>,
+    
+    #[account(mut)]
+    pub play_epoch: Account<'info, PlayEpoch>,
+    
+    #[account(
+        mut,
+        seeds = [PLAY_EPOCH_VAULT_SEED, play_epoch.key().as_ref()],
+        bump
+    )]
+    pub play_epoch_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = funder_payment_account.owner == funder.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = funder_payment_account.mint == play_epoch.payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub funder_payment_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateTrackPlays<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub music_track: Account<'info, MusicTrack>,
+    
+    pub play_epoch: Account<'info, PlayEpoch>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 8 + 8 + 1 + 1,
+        seeds = [TRACK_PLAYS_SEED, play_epoch.key().as_ref(), music_track.key().as_ref()],
+        bump
+    )]
+    pub track_plays: Account<'info, TrackPlays>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AttestPlays<'info> {
+    #[account(mut)]
+    pub attestor: Signer<'info>,
+    
+    #[account(
+        seeds = [AUTH_REGISTRY_SEED],
+        bump,
+        constraint = auth_registry.play_attestor == attestor.key() @ ErrorCode::UnauthorizedAttestor
+    )]
+    pub auth_registry: Account<'info, AuthorizationRegistry>,
+    
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(mut)]
+    pub play_epoch: Account<'info, PlayEpoch>,
+    
+    #[account(
+        init_if_needed,
+        payer = attestor,
+        space = 8 + 32 + 8 + 8 + 1 + 1,
+        seeds = [TRACK_PLAYS_SEED, play_epoch.key().as_ref(), music_track.key().as_ref()],
+        bump
+    )]
+    pub track_plays: Account<'info, TrackPlays>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPlayRoyalties<'info> {
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(mut)]
+    pub play_epoch: Account<'info, PlayEpoch>,
+    
+    #[account(
+        mut,
+        seeds = [PLAY_EPOCH_VAULT_SEED, play_epoch.key().as_ref()],
+        bump
+    )]
+    pub play_epoch_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [TRACK_PLAYS_SEED, play_epoch.key().as_ref(), music_track.key().as_ref()],
+        bump = track_plays.bump,
+        constraint = !track_plays.claimed @ ErrorCode::PlaysAlreadyClaimed
+    )]
+    pub track_plays: Account<'info, TrackPlays>,
+    
+    /// CHECK: Royalty config PDA of the track's royalty mint, it may not exist
+    #[account(
+        seeds = [ROYALTY_CONFIG_SEED, music_track.royalty_mint.as_ref()],
+        bump
+    )]
+    pub royalty_config: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        constraint = artist_payment_account.owner == music_track.authority @ ErrorCode::InvalidTokenAccount,
+        constraint = artist_payment_account.mint == play_epoch.payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub artist_payment_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InvalidateUserAuthCache<'info> {
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(
+        mut,
+        seeds = [USER_AUTH_SEED, user_auth.user.as_ref(), music_track.key().as_ref()],
+        bump
+    )]
+    pub user_auth: Account<'info, UserAuthCache>,
+}
+
+#[derive(Accounts)]
+pub struct CreateUserAuthCache<'info> {
+    pub user: Signer<'info>,
+    
+    // Pays the rent and gets it back on close, the user themselves or a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub music_track: Account<'info, MusicTrack>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = get_user_auth_cache_size(music_track.track_id.len()),
+        seeds = [USER_AUTH_SEED, user.key().as_ref(), music_track.key().as_ref()],
+        bump
+    )]
+    pub user_auth: Account<'info, UserAuthCache>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUserAuthCache<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        close = payer,
+        constraint = user_auth.user == user.key() @ ErrorCode::UnauthorizedPlayback
+    )]
+    pub user_auth: Account<'info, UserAuthCache>,
+    
+    /// CHECK: Receives the rent, must be the account that paid for the cache
+    #[account(mut, address = user_auth.payer)]
+    pub payer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SweepUserAuthCache<'info> {
+    #[account(mut, close = payer)]
+    pub user_auth: Account<'info, UserAuthCache>,
+    
+    /// CHECK: Receives the rent, must be the account that paid for the cache
+    #[account(mut, address = user_auth.payer)]
+    pub payer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLegacyUserAuthCache<'info> {
+    #[account(
+        mut,
+        close = user,
+        seeds = [USER_AUTH_SEED, user.key().as_ref(), user_auth.track_id.as_bytes()],
+        bump
+    )]
+    pub user_auth: Account<'info, LegacyUserAuthCache>,
+    
+    /// CHECK: Receives the rent, legacy caches were always paid for by their user
+    #[account(mut, address = user_auth.user)]
+    pub user: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyPlaybackAuthorization<'info> {
+    /// CHECK: Listener being verified, signs as authority or through a session key
+    pub user: AccountInfo<'info>,
+    
+    // The user, or a session key of theirs
+    pub authority: Signer<'info>,
+    
+    // Optional: Session letting authority verify playback for the user
+    pub session: Option<Account<'info, SessionKey>>,
+    
+    pub music_track: Account<'info, MusicTrack>,
+    
+    // Optional: User's token account for the NFT
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+    
+    // Optional: NFT metadata account
+    /// CHECK: Verified in the instruction
+    pub nft_metadata: Option<AccountInfo<'info>>,
+    
+    /// CHECK: This is the Metaplex Token Metadata program
+    pub metadata_program: AccountInfo<'info>,
+    
+    // Optional: Time pass bought with purchase_access
+    pub access_pass: Option<Account<'info, AccessPass>>,
+    
+    // Optional: Subscription to the track's artist
+    pub subscription: Option<Account<'info, Subscription>>,
+    
+    // Optional: Delegation from the holder of user_token_account to the user
+    pub delegation: Option<Account<'info, ListeningDelegation>>,
+    
+    // Optional: Rental of the NFT in user_token_account, lets a renter play the frozen NFT
+    pub rental: Option<Account<'info, Rental>>,
+    
+    // Optional: User authorization cache, re-verified and re-stamped when expired or fresh
+    #[account(mut)]
+    pub user_auth: Option<Account<'info, UserAuthCache>>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub clock: Sysvar<'info, Clock>,
+}
+
+// Define royalty-related account structures
+
+#[account]
+pub struct RoyaltyConfig {
+    pub mint: Pubkey,                         // NFT mint this config belongs to
+    pub total_basis_points: u16,              // Total royalty (e.g., 1000 = 10%)
+    pub recipients: Vec<RoyaltyRecipient>,    // List of recipients
+    pub authority: Pubkey,                    // Who can modify this config
+    pub is_mutable: bool,                     // Whether config can be changed
+    pub bump: u8,                             // PDA bump seed for easier CPI
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RoyaltyRecipient {
+    pub recipient: Pubkey,                    // Recipient wallet
+    pub basis_points: u16,                    // Share (e.g., 500 = 5%)
+    pub recipient_type: RecipientType,        // Artist, platform, collaborator, etc.
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum RecipientType {
+    Artist,
+    Platform,
+    Collaborator,
+    Other,
+}
+
+// Account validation structures for royalty operations
+
+#[derive(Accounts)]
+#[instruction(total_basis_points: u16, recipients: Vec<RoyaltyRecipient>)]
+pub struct CreateRoyaltyConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub nft_mint: Account<'info, Mint>,
+    
+    // Verify authority is connected to the NFT by requiring their token account
+    #[account(
+        constraint = nft_token_account.mint == nft_mint.key(),
+        constraint = nft_token_account.owner == authority.key(),
+        constraint = nft_token_account.amount > 0
+    )]
+    pub nft_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = get_royalty_config_size(recipients.len()),
+        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub royalty_config: Account<'info, RoyaltyConfig>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRoyaltyConfig<'info> {
+    #[account(
+        constraint = authority.key() == royalty_config.authority @ ErrorCode::UnauthorizedRoyaltyUpdate
+    )]
+    pub authority: Signer<'info>,
+    
+    pub nft_mint: Account<'info, Mint>,
+    
+    // Add optional NFT ownership verification
+    // This allows either the original authority OR the current NFT owner to update royalties
+    // if the config is mutable. Checked in the instruction when provided
+    pub nft_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
         bump = royalty_config.bump,
         constraint = royalty_config.mint == nft_mint.key() @ ErrorCode::InvalidRoyaltyConfig
     )]
-    pub royalty_config: Account<'info, RoyaltyConfig>,
-    
+    pub royalty_config: Account<'info, RoyaltyConfig>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Add helper to find royalty config PDA for a given mint
+pub fn find_royalty_config_pda(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ROYALTY_CONFIG_SEED, mint.as_ref()], program_id)
+}
+
+// Helper function to calculate royalty amount
+fn calculate_royalty_amount(sale_price: u64, basis_points: u16) -> Result<u64> {
+    // Use checked operations and return appropriate error
+    (sale_price as u128)
+        .checked_mul(basis_points as u128)
+        .and_then(|product| product.checked_div(10000))
+        .map(|quotient| quotient as u64)
+        .ok_or(ErrorCode::ArithmeticOverflow.into())
+}
+
+// Helper function to calculate recipient share with proper rounding
+fn calculate_recipient_share(
+    total_royalty_amount: u64,
+    recipient_basis_points: u16,
+    total_basis_points: u16
+) -> Result<u64> {
+    // Use checked operations and return appropriate error
+    let product = (total_royalty_amount as u128)
+        .checked_mul(recipient_basis_points as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    // Round up to avoid dust amounts that can get trapped
+    let divisor = total_basis_points as u128;
+    let quotient = product.checked_div(divisor).ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    // Ensure we're not overflowing u64 when casting back
+    if quotient > u64::MAX as u128 {
+        return Err(ErrorCode::ArithmeticOverflow.into());
+    }
+    
+    Ok(quotient as u64)
+}
+
+// Add a batch processing function for royalty recipients
+// This reduces separate CPI calls when possible by combining recipients with same properties
+fn batch_royalty_recipients(
+    recipients: &[RoyaltyRecipient], 
+    total_royalty_amount: u64,
+    total_basis_points: u16
+) -> Result<Vec<(Pubkey, u64)>> {
+    // Create a map to group recipients by their address
+    let mut recipient_map: std::collections::HashMap<Pubkey, u64> = std::collections::HashMap::new();
+    
+    // Process each recipient
+    for recipient in recipients {
+        let recipient_share = calculate_recipient_share(
+            total_royalty_amount,
+            recipient.basis_points,
+            total_basis_points
+        )?;
+        
+        if recipient_share > 0 {
+            // Add to existing entry or create new one
+            *recipient_map.entry(recipient.recipient).or_insert(0) += recipient_share;
+        }
+    }
+    
+    // Convert map to vector for easier iteration
+    let mut result = Vec::with_capacity(recipient_map.len());
+    for (pubkey, amount) in recipient_map {
+        result.push((pubkey, amount));
+    }
+    
+    Ok(result)
+}
+
+// Pay `sale_price` out of `payment_account`: royalty recipients of `royalty_config` first, the
+// seller gets the rest. Recipient token accounts are passed in `remaining_accounts` in the same
+// order as the config. `signers_seeds` is empty when `payment_authority` signs the transaction.
+#[allow(clippy::too_many_arguments)]
+fn pay_with_royalties<'info>(
+    token_program: &AccountInfo<'info>,
+    payment_account: &Account<'info, TokenAccount>,
+    payment_authority: &AccountInfo<'info>,
+    seller_payment_account: &Account<'info, TokenAccount>,
+    royalty_config: Option<&RoyaltyConfig>,
+    remaining_accounts: &[AccountInfo<'info>],
+    sale_price: u64,
+    signers_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let transfer_to = |to: &AccountInfo<'info>, amount: u64| -> Result<()> {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                Transfer {
+                    from: payment_account.to_account_info(),
+                    to: to.clone(),
+                    authority: payment_authority.clone(),
+                },
+                signers_seeds,
+            ),
+            amount,
+        )
+    };
+
+    // Early return if no royalty is configured
+    let royalty_config = match royalty_config {
+        Some(royalty_config) if royalty_config.total_basis_points > 0 && !royalty_config.recipients.is_empty() => royalty_config,
+        _ => return transfer_to(&seller_payment_account.to_account_info(), sale_price),
+    };
+
+    // Calculate total royalty amount
+    let total_royalty_amount = calculate_royalty_amount(
+        sale_price,
+        royalty_config.total_basis_points
+    )?;
+
+    // If royalty amount is 0, skip royalty distribution
+    if total_royalty_amount == 0 {
+        return transfer_to(&seller_payment_account.to_account_info(), sale_price);
+    }
+
+    // Verify the payer has enough funds for the entire transaction
+    require!(
+        payment_account.amount >= sale_price,
+        ErrorCode::InsufficientFunds
+    );
+
+    // Get the payment token mint for validating recipient accounts
+    let payment_mint = payment_account.mint;
+
+    require!(
+        remaining_accounts.len() >= royalty_config.recipients.len(),
+        ErrorCode::MissingExpectedAccount
+    );
+
+    // Verify seller token account uses the same mint
+    require!(
+        seller_payment_account.mint == payment_mint,
+        ErrorCode::TokenMintMismatch
+    );
+
+    // Pre-validate all recipient accounts to avoid partial execution
+    let mut recipient_account_map = std::collections::HashMap::new();
+    for (i, recipient) in royalty_config.recipients.iter().enumerate() {
+        let recipient_account_info = &remaining_accounts[i];
+
+        // Verify recipient account is a valid token account
+        let recipient_account = Account::<TokenAccount>::try_from(recipient_account_info)?;
+
+        // Verify recipient token account uses the same mint as the payment
+        require!(
+            recipient_account.mint == payment_mint,
+            ErrorCode::TokenMintMismatch
+        );
+
+        // The caller picks these accounts, so make sure they really belong to the recipient
+        require!(
+            recipient_account.owner == recipient.recipient,
+            ErrorCode::InvalidRecipientAccount
+        );
+
+        // Add to map for later use
+        recipient_account_map.insert(recipient.recipient, recipient_account_info);
+    }
+
+    // Batch process recipients to minimize CPI calls
+    let batched_recipients = batch_royalty_recipients(
+        &royalty_config.recipients,
+        total_royalty_amount,
+        royalty_config.total_basis_points
+    )?;
+
+    // Calculate total royalty amount to be paid
+    let mut total_royalties_paid: u64 = 0;
+    for (_, amount) in &batched_recipients {
+        total_royalties_paid = total_royalties_paid.checked_add(*amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    // Calculate seller amount
+    let seller_amount = sale_price.checked_sub(total_royalties_paid)
+        .ok_or(ErrorCode::RoyaltyBasisPointsExceedMax)?;
+
+    // Now execute the transfers with the batched amounts
+    for (recipient_pubkey, amount) in batched_recipients {
+        if let Some(recipient_account_info) = recipient_account_map.get(&recipient_pubkey) {
+            transfer_to(recipient_account_info, amount)?;
+        } else {
+            return Err(ErrorCode::InvalidRecipientAccount.into());
+        }
+    }
+
+    // Skip transfer if amount is zero (unlikely but possible edge case)
+    if seller_amount > 0 {
+        transfer_to(&seller_payment_account.to_account_info(), seller_amount)?;
+    }
+
+    Ok(())
+}
+
+// Load the royalty config at its PDA if one was created for the mint. Taking the PDA itself
+// rather than an optional account means buyers can't skip royalties by leaving it out.
+fn load_royalty_config(royalty_config_info: &AccountInfo, program_id: &Pubkey) -> Result<Option<RoyaltyConfig>> {
+    if royalty_config_info.owner != program_id || royalty_config_info.data_is_empty() {
+        return Ok(None);
+    }
+    let data = royalty_config_info.try_borrow_data()?;
+    Ok(Some(RoyaltyConfig::try_deserialize(&mut &data[..])?))
+}
+
+// Fixed-price marketplace listings
+
+#[account]
+pub struct Listing {
+    pub seller: Pubkey,                       // Wallet that listed the NFT
+    pub nft_mint: Pubkey,                     // NFT held in the listing vault
+    pub payment_mint: Pubkey,                 // Token the price is paid in
+    pub seller_payment_account: Pubkey,       // Seller token account for the proceeds
+    pub price: u64,                           // Fixed sale price
+    pub bump: u8,                             // PDA bump seed
+}
+
+#[derive(Accounts)]
+pub struct List<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = seller_nft_account.mint == nft_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = seller_nft_account.owner == seller.key() @ ErrorCode::InvalidNFTOwnership,
+        constraint = seller_nft_account.amount == 1 @ ErrorCode::InvalidNFTOwnership
+    )]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(
+        constraint = seller_payment_account.mint == payment_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = seller_payment_account.owner == seller.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub seller_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + 32 + 32 + 32 + 32 + 8 + 1,
+        seeds = [LISTING_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(
+        init,
+        payer = seller,
+        token::mint = nft_mint,
+        token::authority = listing,
+        seeds = [LISTING_VAULT_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub listing_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Delist<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = seller_nft_account.mint == nft_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = seller_nft_account.owner == seller.key() @ ErrorCode::InvalidNFTOwnership
+    )]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [LISTING_SEED, nft_mint.key().as_ref()],
+        bump = listing.bump,
+        constraint = listing.seller == seller.key() @ ErrorCode::InvalidListing,
+        close = seller
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(
+        mut,
+        seeds = [LISTING_VAULT_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub listing_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Buy<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Seller recorded in the listing, receives the listing rent
+    #[account(
+        mut,
+        address = listing.seller @ ErrorCode::InvalidListing
+    )]
+    pub seller: AccountInfo<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [LISTING_SEED, nft_mint.key().as_ref()],
+        bump = listing.bump,
+        close = seller
+    )]
+    pub listing: Account<'info, Listing>,
+
+    #[account(
+        mut,
+        seeds = [LISTING_VAULT_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub listing_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = nft_mint,
+        associated_token::authority = buyer
+    )]
+    pub buyer_nft_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Royalty config PDA of the NFT, it may not exist
+    #[account(
+        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub royalty_config: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = buyer_payment_account.mint == listing.payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = listing.seller_payment_account @ ErrorCode::InvalidListing
+    )]
+    pub seller_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Offers on NFTs that aren't listed
+
+#[account]
+pub struct Offer {
+    pub buyer: Pubkey,                        // Wallet that made the offer
+    pub nft_mint: Pubkey,                     // NFT the offer is for
+    pub payment_mint: Pubkey,                 // Token the offer is paid in
+    pub price: u64,                           // Escrowed offer amount
+    pub expires_at: i64,                      // Offer can't be accepted after this (unix timestamp)
+    pub bump: u8,                             // PDA bump seed
+}
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = buyer_payment_account.mint == payment_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 32 + 32 + 8 + 8 + 1,
+        seeds = [OFFER_SEED, nft_mint.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = payment_mint,
+        token::authority = offer,
+        seeds = [OFFER_VAULT_SEED, offer.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [OFFER_SEED, nft_mint.key().as_ref(), buyer.key().as_ref()],
+        bump = offer.bump,
+        close = buyer
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(
+        mut,
+        seeds = [OFFER_VAULT_SEED, offer.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = buyer_payment_account.mint == offer.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// CHECK: Buyer recorded in the offer, receives the offer rent
+    #[account(
+        mut,
+        address = offer.buyer @ ErrorCode::InvalidOffer
+    )]
+    pub buyer: AccountInfo<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = seller_nft_account.mint == nft_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = seller_nft_account.owner == seller.key() @ ErrorCode::InvalidNFTOwnership,
+        constraint = seller_nft_account.amount == 1 @ ErrorCode::InvalidNFTOwnership
+    )]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [OFFER_SEED, nft_mint.key().as_ref(), buyer.key().as_ref()],
+        bump = offer.bump,
+        close = buyer
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(
+        mut,
+        seeds = [OFFER_VAULT_SEED, offer.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = buyer
+    )]
+    pub buyer_nft_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Royalty config PDA of the NFT, it may not exist
+    #[account(
+        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub royalty_config: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = seller_payment_account.mint == offer.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = seller_payment_account.owner == seller.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub seller_payment_account: Account<'info, TokenAccount>,
+
+    // Gets back whatever is left in the vault once the offer is paid out
+    #[account(
+        mut,
+        constraint = buyer_payment_account.mint == offer.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Standing offers for any NFT of a verified collection
+
+#[account]
+pub struct CollectionOffer {
+    pub buyer: Pubkey,                        // Wallet that made the offer
+    pub collection_mint: Pubkey,              // Verified collection the NFTs must belong to
+    pub payment_mint: Pubkey,                 // Token the offer is paid in
+    pub price: u64,                           // Price paid per NFT
+    pub max_items: u32,                       // Number of NFTs the buyer wants
+    pub filled: u32,                          // Number of NFTs bought so far
+    pub expires_at: i64,                      // Offer can't be filled after this (unix timestamp)
+    pub bump: u8,                             // PDA bump seed
+}
+
+#[derive(Accounts)]
+pub struct MakeCollectionOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub collection_mint: Account<'info, Mint>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = buyer_payment_account.mint == payment_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + 32 + 32 + 32 + 8 + 4 + 4 + 8 + 1,
+        seeds = [COLLECTION_OFFER_SEED, collection_mint.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub collection_offer: Account<'info, CollectionOffer>,
+
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = payment_mint,
+        token::authority = collection_offer,
+        seeds = [COLLECTION_OFFER_VAULT_SEED, collection_offer.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCollectionOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub collection_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTION_OFFER_SEED, collection_mint.key().as_ref(), buyer.key().as_ref()],
+        bump = collection_offer.bump,
+        close = buyer
+    )]
+    pub collection_offer: Account<'info, CollectionOffer>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTION_OFFER_VAULT_SEED, collection_offer.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = buyer_payment_account.mint == collection_offer.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct FillCollectionOffer<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// CHECK: Buyer recorded in the collection offer, owns the NFT account it is bought into
+    #[account(address = collection_offer.buyer @ ErrorCode::InvalidOffer)]
+    pub buyer: AccountInfo<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    /// CHECK: Token Metadata account of the NFT, parsed in the instruction
+    pub nft_metadata: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = seller_nft_account.mint == nft_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = seller_nft_account.owner == seller.key() @ ErrorCode::InvalidNFTOwnership,
+        constraint = seller_nft_account.amount == 1 @ ErrorCode::InvalidNFTOwnership
+    )]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTION_OFFER_SEED, collection_offer.collection_mint.as_ref(), buyer.key().as_ref()],
+        bump = collection_offer.bump
+    )]
+    pub collection_offer: Account<'info, CollectionOffer>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTION_OFFER_VAULT_SEED, collection_offer.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = buyer
+    )]
+    pub buyer_nft_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Royalty config PDA of the NFT, it may not exist
+    #[account(
+        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub royalty_config: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = seller_payment_account.mint == collection_offer.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = seller_payment_account.owner == seller.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub seller_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CloseFilledCollectionOffer<'info> {
+    /// CHECK: Buyer recorded in the collection offer, receives the rent
+    #[account(
+        mut,
+        address = collection_offer.buyer @ ErrorCode::InvalidOffer
+    )]
+    pub buyer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTION_OFFER_SEED, collection_offer.collection_mint.as_ref(), buyer.key().as_ref()],
+        bump = collection_offer.bump,
+        constraint = collection_offer.filled == collection_offer.max_items @ ErrorCode::CollectionOfferNotFilled,
+        close = buyer
+    )]
+    pub collection_offer: Account<'info, CollectionOffer>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTION_OFFER_VAULT_SEED, collection_offer.key().as_ref()],
+        bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = buyer_payment_account.mint == collection_offer.payment_mint @ ErrorCode::TokenMintMismatch,
+        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+pub struct Rental {
+    pub owner: Pubkey,                        // Wallet that listed the NFT for rent
+    pub nft_mint: Pubkey,                     // NFT held in the rental vault between rentals
+    pub payment_mint: Pubkey,                 // Token rent is paid in
+    pub owner_payment_account: Pubkey,        // Owner token account for the rent
+    pub daily_price: u64,                     // Rent per day
+    pub max_days: u16,                        // Longest term a renter can pay for
+    pub renter: Pubkey,                       // Current renter, default while available
+    pub renter_nft_account: Pubkey,           // Renter token account holding the frozen NFT
+    pub rented_until: i64,                    // End of the current term (unix timestamp)
+    pub bump: u8,                             // PDA bump seed
+}
+
+impl Rental {
+    // Whether the NFT is out with a renter whose term hasn't ended
+    pub fn is_active(&self, now: i64) -> bool {
+        self.renter != Pubkey::default() && self.rented_until > now
+    }
+}
+
+#[derive(Accounts)]
+pub struct ListRental<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = owner_nft_account.mint == nft_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = owner_nft_account.owner == owner.key() @ ErrorCode::InvalidNFTOwnership,
+        constraint = owner_nft_account.amount == 1 @ ErrorCode::InvalidNFTOwnership
+    )]
+    pub owner_nft_account: Account<'info, TokenAccount>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(
+        constraint = owner_payment_account.mint == payment_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = owner_payment_account.owner == owner.key() @ ErrorCode::InvalidTokenAccount
+    )]
+    pub owner_payment_account: Account<'info, TokenAccount>,
+
     #[account(
-        mut,
-        constraint = buyer_payment_account.owner == buyer.key() @ ErrorCode::InvalidNFTOwnership
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + 32 + 32 + 8 + 2 + 32 + 32 + 8 + 1,
+        seeds = [RENTAL_SEED, nft_mint.key().as_ref()],
+        bump
     )]
-    pub buyer_payment_account: Account<'info, TokenAccount>,
-    
+    pub rental: Account<'info, Rental>,
+
+    #[account(
+        init,
+        payer = owner,
+        token::mint = nft_mint,
+        token::authority = rental,
+        seeds = [RENTAL_VAULT_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub rental_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RentNft<'info> {
     #[account(mut)]
-    pub seller_payment_account: Account<'info, TokenAccount>,
-    
+    pub renter: Signer<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    /// CHECK: Master edition of the NFT, validated by the token metadata program
+    pub nft_edition: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [RENTAL_SEED, nft_mint.key().as_ref()],
+        bump = rental.bump,
+        constraint = rental.renter == Pubkey::default() @ ErrorCode::NftAlreadyRented
+    )]
+    pub rental: Box<Account<'info, Rental>>,
+
+    #[account(
+        mut,
+        seeds = [RENTAL_VAULT_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub rental_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = renter,
+        associated_token::mint = nft_mint,
+        associated_token::authority = renter
+    )]
+    pub renter_nft_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Royalty config PDA of the NFT, it may not exist
+    #[account(
+        seeds = [ROYALTY_CONFIG_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub royalty_config: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = renter_payment_account.owner == renter.key() @ ErrorCode::InvalidTokenAccount,
+        constraint = renter_payment_account.mint == rental.payment_mint @ ErrorCode::TokenMintMismatch
+    )]
+    pub renter_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = rental.owner_payment_account @ ErrorCode::InvalidRental
+    )]
+    pub owner_payment_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the Metaplex Token Metadata program
+    #[account(address = Pubkey::from_str(METADATA_PROGRAM_ID).unwrap() @ ErrorCode::InvalidMetadataProgram)]
+    pub metadata_program: AccountInfo<'info>,
+
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
-// Add a secure helper function for validating royalty payments using the stored bump
-pub fn verify_and_process_royalty_payment(
-    program_id: &Pubkey,
-    nft_mint: &Pubkey,
-    bump: u8,
-    payment_account: &AccountInfo,
-    recipient_account: &AccountInfo,
-    amount: u64,
-    token_program: &AccountInfo
+#[derive(Accounts)]
+pub struct EndRental<'info> {
+    pub nft_mint: Account<'info, Mint>,
+
+    /// CHECK: Master edition of the NFT, validated by the token metadata program
+    pub nft_edition: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [RENTAL_SEED, nft_mint.key().as_ref()],
+        bump = rental.bump,
+        constraint = rental.renter != Pubkey::default() @ ErrorCode::InvalidRental
+    )]
+    pub rental: Account<'info, Rental>,
+
+    #[account(
+        mut,
+        seeds = [RENTAL_VAULT_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub rental_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = rental.renter_nft_account @ ErrorCode::InvalidRental
+    )]
+    pub renter_nft_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the Metaplex Token Metadata program
+    #[account(address = Pubkey::from_str(METADATA_PROGRAM_ID).unwrap() @ ErrorCode::InvalidMetadataProgram)]
+    pub metadata_program: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DelistRental<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub nft_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = owner_nft_account.mint == nft_mint.key() @ ErrorCode::TokenMintMismatch,
+        constraint = owner_nft_account.owner == owner.key() @ ErrorCode::InvalidNFTOwnership
+    )]
+    pub owner_nft_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [RENTAL_SEED, nft_mint.key().as_ref()],
+        bump = rental.bump,
+        constraint = rental.owner == owner.key() @ ErrorCode::InvalidRental,
+        constraint = rental.renter == Pubkey::default() @ ErrorCode::NftAlreadyRented,
+        close = owner
+    )]
+    pub rental: Account<'info, Rental>,
+
+    #[account(
+        mut,
+        seeds = [RENTAL_VAULT_SEED, nft_mint.key().as_ref()],
+        bump
+    )]
+    pub rental_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Minimal reader for the Borsh encoded Token Metadata account
+struct MetadataReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> MetadataReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset.checked_add(len).ok_or(ErrorCode::InvalidNFTMetadata)?;
+        let bytes = self.data.get(self.offset..end).ok_or(ErrorCode::InvalidNFTMetadata)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_pubkey(&mut self) -> Result<Pubkey> {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(self.take(32)?);
+        Ok(Pubkey::new_from_array(bytes))
+    }
+
+    fn skip_string(&mut self) -> Result<()> {
+        let len = self.read_u32()? as usize;
+        self.take(len)?;
+        Ok(())
+    }
+}
+
+// Return the collection of `mint` if its Token Metadata account marks it as verified.
+// Fields after `is_mutable` were added in later metadata versions and read as `None`
+// when an old account doesn't have them.
+fn verified_collection(metadata_info: &AccountInfo, mint: &Pubkey) -> Result<Option<Pubkey>> {
+    let metadata_program_id = Pubkey::from_str(METADATA_PROGRAM_ID).unwrap();
+    require!(
+        *metadata_info.owner == metadata_program_id,
+        ErrorCode::InvalidNFTMetadata
+    );
+
+    let (expected_metadata_key, _) = Pubkey::find_program_address(
+        &[METADATA_PREFIX, metadata_program_id.as_ref(), mint.as_ref()],
+        &metadata_program_id,
+    );
+    require!(
+        metadata_info.key() == expected_metadata_key,
+        ErrorCode::InvalidNFTMetadata
+    );
+
+    let data = metadata_info.try_borrow_data()?;
+    let mut reader = MetadataReader { data: &data, offset: 0 };
+
+    require!(reader.read_u8()? == METADATA_V1_KEY, ErrorCode::InvalidNFTMetadata);
+    reader.read_pubkey()?; // update_authority
+    require!(reader.read_pubkey()? == *mint, ErrorCode::InvalidNFTMetadata);
+    reader.skip_string()?; // name
+    reader.skip_string()?; // symbol
+    reader.skip_string()?; // uri
+    reader.take(2)?; // seller_fee_basis_points
+    if reader.read_u8()? == 1 {
+        let creator_count = reader.read_u32()? as usize;
+        reader.take(creator_count * METADATA_CREATOR_LEN)?;
+    }
+    reader.take(2)?; // primary_sale_happened, is_mutable
+
+    // edition_nonce: Option<u8>, token_standard: Option<TokenStandard>
+    for _ in 0..2 {
+        match reader.read_u8() {
+            Ok(1) => { reader.take(1)?; }
+            Ok(_) => {}
+            Err(_) => return Ok(None),
+        }
+    }
+
+    // collection: Option<Collection { verified: bool, key: Pubkey }>
+    match reader.read_u8() {
+        Ok(1) => {
+            let verified = reader.read_u8()? == 1;
+            let key = reader.read_pubkey()?;
+            Ok(if verified { Some(key) } else { None })
+        }
+        _ => Ok(None),
+    }
+}
+
+// Freeze or thaw a delegated token account through the Token Metadata program.
+// The delegate signs and the NFT's master edition acts as the freeze authority.
+#[allow(clippy::too_many_arguments)]
+fn toggle_delegated_freeze<'info>(
+    instruction: u8,
+    metadata_program: &AccountInfo<'info>,
+    delegate: &AccountInfo<'info>,
+    token_account: &AccountInfo<'info>,
+    edition: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    signers_seeds: &[&[&[u8]]],
 ) -> Result<()> {
-    let seeds = &[
-        ROYALTY_CONFIG_SEED,
-        nft_mint.as_ref(),
-        &[bump]
-    ];
-    let signer = &[&seeds[..]];
-    
-    token::transfer(
-        CpiContext::new_with_signer(
+    require!(
+        metadata_program.key() == Pubkey::from_str(METADATA_PROGRAM_ID).unwrap(),
+        ErrorCode::InvalidMetadataProgram
+    );
+    
+    let ix = Instruction {
+        program_id: metadata_program.key(),
+        accounts: vec![
+            AccountMeta::new(delegate.key(), true),
+            AccountMeta::new(token_account.key(), false),
+            AccountMeta::new_readonly(edition.key(), false),
+            AccountMeta::new_readonly(mint.key(), false),
+            AccountMeta::new_readonly(token_program.key(), false),
+        ],
+        data: vec![instruction],
+    };
+
+    invoke_signed(
+        &ix,
+        &[
+            delegate.clone(),
+            token_account.clone(),
+            edition.clone(),
+            mint.clone(),
             token_program.clone(),
-            Transfer {
-                from: payment_account.clone(),
-                to: recipient_account.clone(),
-                authority: payment_account.clone(),
-            },
-            signer
-        ),
-        amount
+            metadata_program.clone(),
+        ],
+        signers_seeds,
+    )?;
+
+    Ok(())
+}
+
+// Outcome of looking up the hash of the first block at or after a slot
+enum SlotHashLookup {
+    Found([u8; 32]),
+    NotReached,                     // No block at or after the slot yet
+    Expired,                        // The sysvar no longer reaches back to the slot
+}
+
+// Find the hash of the first block at or after `slot` in the raw SlotHashes sysvar data.
+// The sysvar is too large to deserialize on-chain, but entries are sorted newest first.
+// Skipped slots have no entry, hence the first block at or after rather than the slot itself.
+fn find_slot_hash(slot_hashes: &AccountInfo, slot: u64) -> Result<SlotHashLookup> {
+    let data = slot_hashes.try_borrow_data()?;
+    // u64 entry count, then (slot: u64, hash: [u8; 32]) entries
+    require!(data.len() >= 8, ErrorCode::InvalidSlotHashes);
+    let count = u64::from_le_bytes(data[..8].try_into().unwrap()) as usize;
+
+    let mut first_at_or_after = None;
+    for entry in data[8..].chunks_exact(8 + 32).take(count) {
+        let entry_slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        if entry_slot < slot {
+            return Ok(first_at_or_after.map_or(SlotHashLookup::NotReached, SlotHashLookup::Found));
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&entry[8..]);
+        if entry_slot == slot {
+            return Ok(SlotHashLookup::Found(hash));
+        }
+        first_at_or_after = Some(hash);
+    }
+
+    // Every remaining entry is after `slot`, the first block after it may have been dropped
+    Ok(if first_at_or_after.is_some() { SlotHashLookup::Expired } else { SlotHashLookup::NotReached })
+}
+
+// Address of the track `track_id` registered by `authority`
+pub fn find_music_track_pda(authority: &Pubkey, track_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[MUSIC_TRACK_SEED, authority.as_ref(), track_id.as_bytes()],
+        &ID,
+    )
+}
+
+// Address a track had before ids were scoped to their authority, see `migrate_music_track`
+pub fn find_legacy_music_track_pda(track_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MUSIC_TRACK_SEED, track_id.as_bytes()], &ID)
+}
+
+// Address of a cache created under the old track id seeds
+pub fn find_legacy_user_auth_pda(user: &Pubkey, track_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[USER_AUTH_SEED, user.as_ref(), track_id.as_bytes()], &ID)
+}
+
+// Address of the authorization cache of `user` for a track
+pub fn find_user_auth_pda(user: &Pubkey, music_track: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[USER_AUTH_SEED, user.as_ref(), music_track.as_ref()],
+        &ID,
+    )
+}
+
+// Helpers for partner programs gating their own features on Orpheus access
+#[cfg(feature = "cpi")]
+pub mod playback_cpi {
+    use super::*;
+    
+    // Check access without failing the caller's transaction when it is denied
+    pub fn check_playback<'info>(
+        ctx: CpiContext<'_, '_, '_, 'info, cpi::accounts::VerifyPlaybackAuthorization<'info>>,
+    ) -> Result<PlaybackAuthorization> {
+        Ok(cpi::check_playback_authorization(ctx)?.get())
+    }
+    
+    // Require access of at least `min_tier`, failing with UnauthorizedPlayback otherwise
+    pub fn require_playback<'info>(
+        ctx: CpiContext<'_, '_, '_, 'info, cpi::accounts::VerifyPlaybackAuthorization<'info>>,
+        min_tier: u8,
+    ) -> Result<PlaybackAuthorization> {
+        let authorization = check_playback(ctx)?;
+        require!(
+            authorization.granted && authorization.tier >= min_tier,
+            ErrorCode::UnauthorizedPlayback
+        );
+        Ok(authorization)
+    }
+}
+
+// Epoch plays are counted in right now
+fn current_play_epoch() -> Result<u64> {
+    Ok((Clock::get()?.unix_timestamp / PLAY_EPOCH_SEC) as u64)
+}
+
+// Work out whether the user may play the track, caching the grant when they may
+fn authorize_playback(
+    accounts: &mut VerifyPlaybackAuthorization,
+    program_id: &Pubkey,
+) -> Result<PlaybackAuthorization> {
+    let music_track = &accounts.music_track;
+    let user = &accounts.user;
+    let now = Clock::get()?.unix_timestamp;
+    let is_released = music_track.is_released(now);
+    
+    // The user signs themselves or through a session key covering this track
+    match &accounts.session {
+        Some(session) => {
+            require!(session.expires_at > now, ErrorCode::SessionKeyExpired);
+            require!(
+                session.covers(&music_track.key(), &music_track.authority),
+                ErrorCode::SessionKeyOutOfScope
+            );
+        }
+        None => require!(accounts.authority.key() == user.key(), ErrorCode::InvalidSessionKey),
+    }
+    
+    // Optional accounts are checked here, Anchor only checks their owner and type
+    if let Some(session) = &accounts.session {
+        require!(
+            session.user == user.key() && session.session_key == accounts.authority.key(),
+            ErrorCode::InvalidSessionKey
+        );
+    }
+    if let Some(access_pass) = &accounts.access_pass {
+        require!(
+            access_pass.user == user.key() && access_pass.music_track == music_track.key(),
+            ErrorCode::InvalidAccessPass
+        );
+    }
+    if let Some(subscription) = &accounts.subscription {
+        require!(
+            subscription.subscriber == user.key() && subscription.artist == music_track.authority,
+            ErrorCode::InvalidSubscription
+        );
+    }
+    if let Some(delegation) = &accounts.delegation {
+        require!(delegation.delegate == user.key(), ErrorCode::InvalidDelegation);
+    }
+    if let Some(user_auth) = &accounts.user_auth {
+        require!(
+            user_auth.key() == find_user_auth_pda(&user.key(), &music_track.key()).0
+                && user_auth.user == user.key()
+                && user_auth.track_id == music_track.track_id,
+            ErrorCode::InvalidAuthCache
+        );
+        // Caches from an older layout are closed and recreated rather than migrated
+        require!(user_auth.version == USER_AUTH_CACHE_VERSION, ErrorCode::OutdatedAuthCache);
+    }
+    
+    // Check if there's a valid cached authorization
+    if let Some(user_auth) = &accounts.user_auth {
+        // Verify cache hasn't expired and what granted it is still authorized
+        if user_auth.expires_at > now && user_auth.is_still_granted(music_track, now) {
+            return Ok(PlaybackAuthorization::granted(PlaybackReason::Cache, user_auth.tier, user_auth.expires_at));
+        } else {
+            // Authorization expired or revoked - continue with verification
+        }
+    }
+    
+    // A purchased time pass grants access on its own once the track is released
+    if let Some(access_pass) = &accounts.access_pass {
+        if is_released && access_pass.expires_at > now {
+            return Ok(PlaybackAuthorization::granted(PlaybackReason::AccessPass, BASE_ACCESS_TIER, access_pass.expires_at));
+        }
+    }
+    
+    // So does an active subscription to the track's artist
+    if let Some(subscription) = &accounts.subscription {
+        if is_released && subscription.expires_at > now {
+            return Ok(PlaybackAuthorization::granted(PlaybackReason::Subscription, BASE_ACCESS_TIER, subscription.expires_at));
+        }
+    }
+    
+    // If track is public, or its holders-only window is over, allow playback without NFT verification
+    if music_track.is_public_at(now) {
+        let expires_at = now + music_track.auth_ttl;
+        // Cache the authorization if cache account provided
+        if let Some(user_auth) = &mut accounts.user_auth {
+            user_auth.user = user.key();
+            user_auth.track_id = music_track.track_id.clone();
+            user_auth.expires_at = expires_at;
+            user_auth.granted_via = Pubkey::default();
+            user_auth.tier = BASE_ACCESS_TIER;
+        }
+        return Ok(PlaybackAuthorization::granted(PlaybackReason::Public, BASE_ACCESS_TIER, expires_at));
+    }
+    
+    // Verify the metadata program is legitimate
+    let expected_metadata_program = 
+        Pubkey::from_str(METADATA_PROGRAM_ID).unwrap();
+    require!(
+        accounts.metadata_program.key() == expected_metadata_program,
+        ErrorCode::InvalidMetadataProgram
+    );
+    
+    // Check if user has provided a token account
+    if let Some(user_token_account) = &accounts.user_token_account {
+        // With a delegation the token account belongs to the delegating holder instead
+        let mut cache_expires_at = now + music_track.auth_ttl;
+        let holder = match &accounts.delegation {
+            Some(delegation) => {
+                require!(delegation.expires_at > now, ErrorCode::DelegationExpired);
+                require!(
+                    delegation.mint == user_token_account.mint
+                        && (delegation.music_track == Pubkey::default()
+                            || delegation.music_track == music_track.key()),
+                    ErrorCode::InvalidDelegation
+                );
+                cache_expires_at = cache_expires_at.min(delegation.expires_at);
+                delegation.holder
+            }
+            None => user.key(),
+        };
+        
+        // Verify the token account belongs to the holder, so a delegation lapses once the NFT moves
+        // In SPL tokens, the 'owner' field is actually the authority who can transfer the tokens
+        require!(
+            user_token_account.owner == holder,
+            ErrorCode::InvalidNFTOwnership
+        );
+        
+        // Verify the token isn't frozen, unless it is frozen by an in-place listing or an active rental
+        let (escrow_pda, _) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], program_id);
+        let rental = accounts.rental
+            .as_ref()
+            .filter(|rental| user_token_account.delegate == COption::Some(rental.key()));
+        if let Some(rental) = rental {
+            require!(rental.nft_mint == user_token_account.mint, ErrorCode::InvalidRental);
+            require!(rental.is_active(now), ErrorCode::InvalidNFTOwnership);
+            cache_expires_at = cache_expires_at.min(rental.rented_until);
+        }
+        require!(
+            !user_token_account.is_frozen()
+                || user_token_account.delegate == COption::Some(escrow_pda)
+                || rental.is_some(),
+            ErrorCode::InvalidNFTOwnership
+        );
+        
+        // Before the release only pre-release collections get in, and fan tokens don't count
+        let nft_mint = user_token_account.mint;
+        let unlocking_collections = music_track.unlocking_collections(now);
+        let token_gate = music_track.token_gates
+            .iter()
+            .find(|gate| gate.mint == nft_mint)
+            .filter(|_| is_released);
+        let granted_via = match token_gate {
+            // Fan tokens only need to meet the gate's minimum balance
+            Some(token_gate) => (user_token_account.amount >= token_gate.min_amount).then_some(nft_mint),
+            None => {
+                require!(
+                    user_token_account.amount == 1,
+                    ErrorCode::InvalidNFTOwnership
+                );
+                
+                // First, check if the NFT mint is directly authorized
+                if unlocking_collections.contains(&nft_mint) {
+                    Some(nft_mint)
+                } else if let Some(metadata_info) = &accounts.nft_metadata {
+                    // Otherwise the NFT must belong to a verified, authorized collection
+                    verified_collection(metadata_info, &nft_mint)?
+                        .filter(|collection| unlocking_collections.contains(collection))
+                } else {
+                    None
+                }
+            }
+        };
+        
+        if let Some(granted_via) = granted_via {
+            let tier = music_track.tier_for(&granted_via);
+            let reason = if token_gate.is_some() {
+                PlaybackReason::TokenGate
+            } else if granted_via == nft_mint {
+                PlaybackReason::Nft
+            } else {
+                PlaybackReason::Collection
+            };
+            // Cache the authorization if cache account provided
+            if let Some(user_auth) = &mut accounts.user_auth {
+                user_auth.user = user.key();
+                user_auth.track_id = music_track.track_id.clone();
+                user_auth.expires_at = cache_expires_at;
+                user_auth.granted_via = granted_via;
+                user_auth.tier = tier;
+            }
+            return Ok(PlaybackAuthorization::granted(reason, tier, cache_expires_at));
+        }
+    }
+    
+    // If we reach here, the user is not authorized
+    if !is_released {
+        return Ok(PlaybackAuthorization::denied(PlaybackReason::NotReleased));
+    }
+    Ok(PlaybackAuthorization::denied(PlaybackReason::Denied))
+}
+
+// Space needed for an EditionAuction with room for `price_level_count` price levels
+fn get_edition_auction_size(price_level_count: usize) -> usize {
+    8 +                     // discriminator
+    8 +                     // auction_id: u64
+    32 * 4 +                // seller, edition_mint, payment_mint, seller_payment_account
+    4 +                     // edition_count: u32
+    8 +                     // reserve_price: u64
+    8 +                     // end_at: i64
+    1 +                     // is_minted: bool
+    1 +                     // is_cleared: bool
+    8 +                     // clearing_price: u64
+    4 +                     // units_sold: u32
+    8 +                     // marginal_units: u64
+    4 +                     // bid_count: u32
+    4 +                     // claimed_count: u32
+    4 +                     // price_levels vec len
+    price_level_count * (
+        8 +                 // price: u64
+        8                   // quantity: u64
+    ) +
+    1                       // bump: u8
+}
+
+// Grow a music track account to fit its contents, `payer` covers the extra rent
+fn grow_music_track<'info>(
+    music_track: &Account<'info, MusicTrack>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    grow_account(
+        &music_track.to_account_info(),
+        get_music_track_size(music_track),
+        payer,
+        system_program,
+    )
+}
+
+// Grow an account to `new_size` if it is smaller, `payer` covers the extra rent
+fn grow_account<'info>(
+    account_info: &AccountInfo<'info>,
+    new_size: usize,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if new_size > account_info.data_len() {
+        let rent_due = Rent::get()?
+            .minimum_balance(new_size)
+            .saturating_sub(account_info.lamports());
+        if rent_due > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    system_program::Transfer {
+                        from: payer.clone(),
+                        to: account_info.clone(),
+                    },
+                ),
+                rent_due,
+            )?;
+        }
+        account_info.realloc(new_size, false)?;
+    }
+    Ok(())
+}
+
+// Read an account written in an older layout, checking the discriminator it was written with
+fn deserialize_legacy_account<T: AnchorDeserialize>(buf: &mut &[u8], discriminator: [u8; 8]) -> Result<T> {
+    if buf.len() < discriminator.len() {
+        return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorNotFound.into());
+    }
+    if buf[..8] != discriminator {
+        return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    let mut data: &[u8] = &buf[8..];
+    T::deserialize(&mut data).map_err(|_| anchor_lang::error::ErrorCode::AccountDidNotDeserialize.into())
+}
+
+// Space needed to hold a MusicTrack with its current URIs and a full collection list
+fn get_music_track_size(music_track: &MusicTrack) -> usize {
+    get_music_track_size_for(
+        &music_track.track_id,
+        [&music_track.track_uri, &music_track.preview_uri, &music_track.metadata_uri],
+        &music_track.tier_uris,
     )
 }
 
-// Add this helper function to create a seeds-with-bump array once and reuse it
-fn get_royalty_config_seeds<'a>(
-    nft_mint: &'a Pubkey,
-    bump: &'a u8,
-) -> [&'a [u8]; 3] {
-    [ROYALTY_CONFIG_SEED, nft_mint.as_ref(), std::slice::from_ref(bump)]
+// Space needed for a MusicTrack with the given id, track, preview and metadata URIs and tier URIs
+fn get_music_track_size_for(track_id: &str, uris: [&String; 3], tier_uris: &[String]) -> usize {
+    8 +                                         // discriminator
+    32 +                                        // authority: Pubkey
+    4 + track_id.len() +                        // track_id: String
+    uris.iter().map(|uri| 4 + uri.len()).sum::<usize>() + // track_uri, preview_uri, metadata_uri: String
+    4 + MAX_AUTHORIZED_COLLECTIONS * 32 +       // authorized_collections: Vec<Pubkey>
+    1 +                                         // is_public: bool
+    8 +                                         // auth_ttl: i64
+    4 + MAX_TOKEN_GATES * (32 + 8) +            // token_gates: Vec<TokenGate>
+    32 +                                        // access_payment_mint: Pubkey
+    32 +                                        // royalty_mint: Pubkey
+    4 + MAX_ACCESS_TIERS * (8 + 8) +            // access_tiers: Vec<AccessTier>
+    8 +                                         // release_at: i64
+    8 +                                         // holders_only_until: i64
+    4 + MAX_PRERELEASE_COLLECTIONS * 32 +       // prerelease_collections: Vec<Pubkey>
+    4 + MAX_AUTHORIZED_COLLECTIONS * (32 + 1) + // collection_tiers: Vec<CollectionTier>
+    4 + tier_uris.iter().map(|uri| 4 + uri.len()).sum::<usize>() // tier_uris: Vec<String>
+}
+
+// Space needed for the AuthorizationRegistry
+fn get_auth_registry_size() -> usize {
+    8 +                     // discriminator
+    32 +                    // authority: Pubkey
+    8 +                     // track_count: u64
+    8 +                     // default_auth_ttl: i64
+    32 +                    // play_attestor: Pubkey
+    1                       // bump: u8
+}
+
+// Space needed for a UserAuthCache of a track with the given id length
+fn get_user_auth_cache_size(track_id_len: usize) -> usize {
+    8 +                     // discriminator
+    1 +                     // version: u8
+    32 +                    // user: Pubkey
+    4 + track_id_len +      // track_id: String
+    8 +                     // expires_at: i64
+    32 +                    // granted_via: Pubkey
+    32 +                    // payer: Pubkey
+    8 +                     // created_at: i64
+    1                       // tier: u8
 }
 
 // Add a helper function to calculate exact space needed for RoyaltyConfig
@@ -1,19 +1,18 @@
-import * as anchor from '@project-serum/anchor';
-import {AnchorProvider, Program} from '@project-serum/anchor';
-import NodeWallet from '@project-serum/anchor/dist/cjs/nodewallet';
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
 import {AnchorAuction} from '../target/types/anchor_auction';
-import {SystemProgram, Connection, LAMPORTS_PER_SOL, PublicKey, Transaction} from '@solana/web3.js';
+import {Connection, LAMPORTS_PER_SOL, PublicKey} from '@solana/web3.js';
 import {
-    AccountLayout,
     AuthorityType,
     createAccount,
-    createInitializeAccountInstruction,
     createMint,
     mintTo,
     setAuthority,
     TOKEN_PROGRAM_ID
 } from "@solana/spl-token";
 import * as assert from "assert";
+import {accountInstructions, getTokenBalance} from './utils';
 
 describe('anchor-auction', () => {
     const connection = new Connection("http://localhost:8899", "confirmed");
@@ -100,6 +99,7 @@ describe('anchor-auction', () => {
                     exhibitorNftTempAccount: exhibitorNftTempAccount.publicKey,
                     exhibitorFtReceivingAccount: exhibitorFtTokenAccountPubkey,
                     escrowAccount: escrowAccount.publicKey,
+                    musicTrack: null,
                     clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
                     tokenProgram: TOKEN_PROGRAM_ID,
                 },
@@ -175,6 +175,9 @@ describe('anchor-auction', () => {
                     highestBidderFtTempAccount: auction.highestBidderFtTempPubkey,
                     highestBidderNftReceivingAccount: winningBidderNftReceivingAccount.publicKey,
                     escrowAccount: escrowAccount.publicKey,
+                    nftMint: null,
+                    nftEdition: null,
+                    metadataProgram: null,
                     clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
                     pda: pda[0],
                     tokenProgram: TOKEN_PROGRAM_ID,
@@ -216,39 +219,6 @@ describe('anchor-auction', () => {
 
 })
 
-async function accountInstructions(connection: Connection, mintPubkey: PublicKey, taPubkey: PublicKey, creatorPubkey: PublicKey) {
-    const createAccount = SystemProgram.createAccount({
-        space: AccountLayout.span,
-        lamports: await connection.getMinimumBalanceForRentExemption(
-            AccountLayout.span
-        ),
-        fromPubkey: creatorPubkey,
-        newAccountPubkey: taPubkey,
-        programId: TOKEN_PROGRAM_ID,
-    });
-    const initAccount = createInitializeAccountInstruction(
-        taPubkey,
-        mintPubkey,
-        creatorPubkey,
-        TOKEN_PROGRAM_ID
-    );
-    return [createAccount, initAccount]
-}
-
-const getTokenBalance = async (
-    pubkey: PublicKey,
-    connection: Connection
-) => {
-    try {
-        return parseInt(
-            (await connection.getTokenAccountBalance(pubkey)).value.amount
-        );
-    } catch (e) {
-        console.error(`Not a token account ${pubkey}`);
-        return NaN;
-    }
-};
-
 async function logAuction(connection: Connection, escrowPubkey: PublicKey, program : Program<AnchorAuction>) {
     const auction = await program.account.auction.fetch(escrowPubkey)
     console.table({
@@ -0,0 +1,264 @@
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
+import {AnchorAuction} from '../target/types/anchor_auction';
+import {Connection, Keypair, PublicKey, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY} from '@solana/web3.js';
+import {
+    TOKEN_PROGRAM_ID
+} from "@solana/spl-token";
+import * as assert from "assert";
+import {accountInstructions, airdrop, createFtMint, createNft, createTokenAccount, getTokenBalance} from './utils';
+
+describe('candle-auction', () => {
+    const connection = new Connection("http://localhost:8899", "confirmed");
+    const options = AnchorProvider.defaultOptions();
+    const wallet = NodeWallet.local();
+    const provider = new AnchorProvider(connection, wallet, options);
+
+    anchor.setProvider(provider);
+    const program = anchor.workspace.AnchorAuction as Program<AnchorAuction>;
+
+    const payerAccount = wallet.payer
+    const exhibitorAccount = Keypair.generate();
+    const bidder1Account = Keypair.generate();
+    const bidder2Account = Keypair.generate();
+    const escrowAccount = Keypair.generate();
+
+    let nftMintPubkey: PublicKey
+    let ftMintPubkey: PublicKey
+    let exhibitorNftTokenAccountPubkey: PublicKey
+    let exhibitorFtTokenAccountPubkey: PublicKey
+    let bidder1FtTokenAccountPubkey: PublicKey
+    let bidder2FtTokenAccountPubkey: PublicKey
+    let pdaPubkey: PublicKey
+    it("Setup", async () => {
+        await airdrop(connection, [exhibitorAccount, bidder1Account, bidder2Account]);
+        ({mint: nftMintPubkey, tokenAccount: exhibitorNftTokenAccountPubkey} = await createNft(connection, payerAccount, exhibitorAccount.publicKey));
+
+        ftMintPubkey = await createFtMint(connection, payerAccount);
+        exhibitorFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, exhibitorAccount.publicKey);
+        bidder1FtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, bidder1Account.publicKey, 500);
+        bidder2FtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, bidder2Account.publicKey, 500);
+
+        [pdaPubkey] = await PublicKey.findProgramAddress([Buffer.from("escrow")], program.programId);
+    });
+
+    const exhibitorNftTempAccount = Keypair.generate();
+    const initialPrice = 100
+    const duration = 12
+    const candleDuration = 6
+    it("Exhibits with a candle window at the end", async () => {
+        const signature = await program.rpc.exhibitCandle(
+            new anchor.BN(initialPrice),
+            new anchor.BN(duration),
+            new anchor.BN(candleDuration),
+            {
+                accounts: {
+                    exhibitor: exhibitorAccount.publicKey,
+                    exhibitorNftTokenAccount: exhibitorNftTokenAccountPubkey,
+                    exhibitorNftTempAccount: exhibitorNftTempAccount.publicKey,
+                    exhibitorFtReceivingAccount: exhibitorFtTokenAccountPubkey,
+                    escrowAccount: escrowAccount.publicKey,
+                    musicTrack: null,
+                    clock: SYSVAR_CLOCK_PUBKEY,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                instructions: [
+                    ...await accountInstructions(connection, nftMintPubkey, exhibitorNftTempAccount.publicKey, exhibitorAccount.publicKey),
+                    await program.account.auction.createInstruction(escrowAccount),
+                ],
+                signers: [exhibitorAccount, exhibitorNftTempAccount, escrowAccount]
+            }
+        );
+        console.log(`exhibit candle tx = ${signature}`)
+
+        const auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        assert.ok(auction.isCandle);
+        assert.equal(auction.endAt.toNumber() - auction.candleStartAt.toNumber(), candleDuration);
+        assert.ok(auction.candleSettleSlot.toNumber() > await connection.getSlot());
+        assert.equal(await getTokenBalance(exhibitorNftTempAccount.publicKey, connection), 1)
+    });
+
+    const bid = async (price: number, bidder: Keypair, bidderFtPubkey: PublicKey) => {
+        const bidderFtTempAccount = Keypair.generate()
+        const auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        const signature = await program.rpc.bid(
+            new anchor.BN(price),
+            {
+                accounts: {
+                    bidder: bidder.publicKey,
+                    bidderFtTempAccount: bidderFtTempAccount.publicKey,
+                    bidderFtAccount: bidderFtPubkey,
+                    highestBidder: auction.highestBidderPubkey,
+                    highestBidderFtTempAccount: auction.highestBidderFtTempPubkey,
+                    highestBidderFtReturningAccount: auction.highestBidderFtReturningPubkey,
+                    escrowAccount: escrowAccount.publicKey,
+                    clock: SYSVAR_CLOCK_PUBKEY,
+                    pda: pdaPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                instructions: [
+                    ...await accountInstructions(connection, ftMintPubkey, bidderFtTempAccount.publicKey, bidder.publicKey),
+                ],
+                signers: [bidder, bidderFtTempAccount]
+            }
+        );
+        console.log(`bid tx = ${signature}`)
+        return bidderFtTempAccount.publicKey
+    };
+
+    let bid1FtTempPubkey: PublicKey
+    let bid2FtTempPubkey: PublicKey
+    it("Keeps outbid funds escrowed once the candle is lit", async function () {
+        this.timeout(30000);
+        bid1FtTempPubkey = await bid(initialPrice + 10, bidder1Account, bidder1FtTokenAccountPubkey);
+        let auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        assert.equal(auction.candleBidCount, 1);
+
+        // sleep until the candle phase has started
+        const candleStartAt = auction.candleStartAt.toNumber();
+        await new Promise((resolve) => setTimeout(resolve, Math.max(0, candleStartAt * 1000 - Date.now()) + 2000));
+
+        bid2FtTempPubkey = await bid(initialPrice + 20, bidder2Account, bidder2FtTokenAccountPubkey);
+        auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        assert.equal(auction.candleBidCount, 2);
+        assert.ok(auction.candleBids[1].placedAt.toNumber() >= candleStartAt);
+
+        // bidder1 could still win, so their bid wasn't refunded
+        assert.equal(await getTokenBalance(bid1FtTempPubkey, connection), initialPrice + 10)
+        assert.equal(await getTokenBalance(bidder1FtTokenAccountPubkey, connection), 500 - initialPrice - 10)
+        assert.equal(await getTokenBalance(bid2FtTempPubkey, connection), initialPrice + 20)
+    });
+
+    const winnerNftReceivingAccount = Keypair.generate();
+    // calls expected to fail pass an existing NFT account, the winner's is only created on the real close
+    const closeAccounts = async (nftReceivingPubkey: PublicKey = exhibitorNftTokenAccountPubkey) => {
+        const auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        return {
+            winningBidder: auction.highestBidderPubkey,
+            exhibitor: auction.exhibitorPubkey,
+            exhibitorNftTempAccount: auction.exhibitingNftTempPubkey,
+            exhibitorFtReceivingAccount: auction.exhibitorFtReceivingPubkey,
+            highestBidderFtTempAccount: auction.highestBidderFtTempPubkey,
+            highestBidderNftReceivingAccount: nftReceivingPubkey,
+            escrowAccount: escrowAccount.publicKey,
+            nftMint: null,
+            nftEdition: null,
+            metadataProgram: null,
+            clock: SYSVAR_CLOCK_PUBKEY,
+            pda: pdaPubkey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        };
+    };
+
+    it("Can't be closed before the cutoff is picked", async function () {
+        this.timeout(30000);
+        const auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        await new Promise((resolve) => setTimeout(resolve, Math.max(0, auction.endAt.toNumber() * 1000 - Date.now()) + 2000));
+
+        await assert.rejects(
+            program.rpc.close({accounts: await closeAccounts(), signers: [bidder2Account]}),
+            (e: any) => e.error?.errorCode?.code === "CandleAuctionNotSettled" || /CandleAuctionNotSettled/.test(e.toString())
+        );
+    });
+
+    let winner: Keypair
+    let loser: {account: Keypair, ftTokenAccount: PublicKey, ftTempAccount: PublicKey, index: number}
+    it("Settles on the last bid placed before the cutoff", async function () {
+        this.timeout(60000);
+        let auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        // the committed slot's hash only exists once the slot has passed
+        while (await connection.getSlot() <= auction.candleSettleSlot.toNumber()) {
+            await new Promise((resolve) => setTimeout(resolve, 1000));
+        }
+
+        const signature = await program.rpc.settleCandle({
+            accounts: {
+                escrowAccount: escrowAccount.publicKey,
+                slotHashes: SYSVAR_SLOT_HASHES_PUBKEY,
+                clock: SYSVAR_CLOCK_PUBKEY,
+            },
+        });
+        console.log(`settle candle tx = ${signature}`)
+
+        auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        const cutoff = auction.candleEndAt.toNumber();
+        assert.ok(auction.candleSettled);
+        assert.ok(cutoff >= auction.candleStartAt.toNumber() && cutoff <= auction.endAt.toNumber());
+
+        // bid1 was placed before the window so it's always eligible, bid2 only if the cutoff came after it
+        if (auction.candleBids[1].placedAt.toNumber() <= cutoff) {
+            winner = bidder2Account;
+            loser = {account: bidder1Account, ftTokenAccount: bidder1FtTokenAccountPubkey, ftTempAccount: bid1FtTempPubkey, index: 0};
+            assert.ok(auction.highestBidderFtTempPubkey.equals(bid2FtTempPubkey));
+            assert.equal(auction.price.toNumber(), initialPrice + 20);
+        } else {
+            winner = bidder1Account;
+            loser = {account: bidder2Account, ftTokenAccount: bidder2FtTokenAccountPubkey, ftTempAccount: bid2FtTempPubkey, index: 1};
+            assert.ok(auction.highestBidderFtTempPubkey.equals(bid1FtTempPubkey));
+            assert.equal(auction.price.toNumber(), initialPrice + 10);
+        }
+        assert.ok(auction.highestBidderPubkey.equals(winner.publicKey));
+    });
+
+    it("Refunds the losing bid before the auction can close", async () => {
+        await assert.rejects(
+            program.rpc.close({accounts: await closeAccounts(), signers: [winner]}),
+            (e: any) => e.error?.errorCode?.code === "CandleRefundsPending" || /CandleRefundsPending/.test(e.toString())
+        );
+
+        // the winning bid can't be refunded
+        await assert.rejects(
+            program.rpc.refundCandleBid(
+                1 - loser.index,
+                {
+                    accounts: {
+                        bidder: winner.publicKey,
+                        bidderFtTempAccount: loser.index == 0 ? bid2FtTempPubkey : bid1FtTempPubkey,
+                        bidderFtReturningAccount: loser.index == 0 ? bidder2FtTokenAccountPubkey : bidder1FtTokenAccountPubkey,
+                        escrowAccount: escrowAccount.publicKey,
+                        pda: pdaPubkey,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                    },
+                }
+            ),
+            (e: any) => e.error?.errorCode?.code === "InvalidCandleBid" || /InvalidCandleBid/.test(e.toString())
+        );
+
+        const signature = await program.rpc.refundCandleBid(
+            loser.index,
+            {
+                accounts: {
+                    bidder: loser.account.publicKey,
+                    bidderFtTempAccount: loser.ftTempAccount,
+                    bidderFtReturningAccount: loser.ftTokenAccount,
+                    escrowAccount: escrowAccount.publicKey,
+                    pda: pdaPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+            }
+        );
+        console.log(`refund candle bid tx = ${signature}`)
+
+        assert.equal(await getTokenBalance(loser.ftTokenAccount, connection), 500)
+        assert.ok(isNaN(await getTokenBalance(loser.ftTempAccount, connection)))
+    });
+
+    it("Delivers the NFT to the candle winner", async () => {
+        const auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        const price = auction.price.toNumber();
+        const signature = await program.rpc.close({
+            accounts: await closeAccounts(winnerNftReceivingAccount.publicKey),
+            instructions: [
+                ...await accountInstructions(connection, nftMintPubkey, winnerNftReceivingAccount.publicKey, winner.publicKey),
+            ],
+            signers: [winner, winnerNftReceivingAccount],
+        });
+        console.log(`close tx = ${signature}`)
+
+        assert.equal(await getTokenBalance(winnerNftReceivingAccount.publicKey, connection), 1)
+        assert.equal(await getTokenBalance(exhibitorFtTokenAccountPubkey, connection), price)
+        assert.ok(isNaN(await getTokenBalance(exhibitorNftTempAccount.publicKey, connection)))
+        assert.equal(await connection.getAccountInfo(escrowAccount.publicKey), null)
+    });
+});
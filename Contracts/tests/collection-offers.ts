@@ -0,0 +1,231 @@
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
+import {AnchorAuction} from '../target/types/anchor_auction';
+import {SystemProgram, Connection, Keypair, PublicKey, SYSVAR_RENT_PUBKEY} from '@solana/web3.js';
+import {
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+    getAssociatedTokenAddress,
+    mintTo,
+    TOKEN_PROGRAM_ID
+} from "@solana/spl-token";
+import * as assert from "assert";
+import {airdrop, createFtMint, createTokenAccount, getTokenBalance} from './utils';
+
+// Accounts below are loaded into the local validator from tests/fixtures, see Anchor.toml.
+// The NFT is held by the seller fixture and verified in a collection whose mint is a real account.
+const COLLECTION_MINT = new PublicKey("AoHdJiVrvHbQmUwqckLew17smdv1CxY5LRJaHyyWrt3R");
+const COLLECTION_NFT = {
+    mint: new PublicKey("9w74SNDpL3jfQHV1MTNoz8HiTewaMRYp2gEjEQVKX49p"),
+    tokenAccount: new PublicKey("DbUo9QE2o8kZz99erRWU5pFxZtviGBBo2BLg9V75tV2V"),
+    metadata: new PublicKey("7XbB8rR9pxJDnGahfU5KosDufT6DeSGs2sHUNgCBJ4TL"),
+};
+
+describe('collection-offers', () => {
+    const connection = new Connection("http://localhost:8899", "confirmed");
+    const options = AnchorProvider.defaultOptions();
+    const wallet = NodeWallet.local();
+    const provider = new AnchorProvider(connection, wallet, options);
+
+    anchor.setProvider(provider);
+    const program = anchor.workspace.AnchorAuction as Program<AnchorAuction>;
+
+    const payerAccount = wallet.payer
+    const sellerAccount = Keypair.fromSecretKey(Uint8Array.from(require("./fixtures/seller.json")));
+    const buyerAccount = Keypair.generate();
+    const artistAccount = Keypair.generate();
+    const platformAccount = Keypair.generate();
+
+    let ftMintPubkey: PublicKey
+    let sellerFtTokenAccountPubkey: PublicKey
+    let buyerFtTokenAccountPubkey: PublicKey
+    let artistFtTokenAccountPubkey: PublicKey
+    let platformFtTokenAccountPubkey: PublicKey
+    let royaltyConfigPubkey: PublicKey
+    it("Setup", async () => {
+        await airdrop(connection, [sellerAccount, buyerAccount]);
+        ftMintPubkey = await createFtMint(connection, payerAccount);
+        sellerFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, sellerAccount.publicKey);
+        buyerFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, buyerAccount.publicKey, 1000);
+        artistFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, artistAccount.publicKey);
+        platformFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, platformAccount.publicKey);
+
+        const [royaltyConfig] = await PublicKey.findProgramAddress([Buffer.from("royalty_config"), COLLECTION_NFT.mint.toBuffer()], program.programId);
+        royaltyConfigPubkey = royaltyConfig;
+        await program.rpc.createRoyaltyConfig(
+            1000,
+            [
+                {recipient: artistAccount.publicKey, basisPoints: 600, recipientType: {artist: {}}},
+                {recipient: platformAccount.publicKey, basisPoints: 400, recipientType: {platform: {}}},
+            ],
+            false,
+            {
+                accounts: {
+                    authority: sellerAccount.publicKey,
+                    nftMint: COLLECTION_NFT.mint,
+                    nftTokenAccount: COLLECTION_NFT.tokenAccount,
+                    royaltyConfig: royaltyConfigPubkey,
+                    systemProgram: SystemProgram.programId,
+                },
+                signers: [sellerAccount],
+            }
+        );
+    });
+
+    const offerPdas = async (collectionMintPubkey: PublicKey, buyerPubkey: PublicKey) => {
+        const [collectionOffer] = await PublicKey.findProgramAddress(
+            [Buffer.from("collection_offer"), collectionMintPubkey.toBuffer(), buyerPubkey.toBuffer()],
+            program.programId
+        );
+        const [offerVault] = await PublicKey.findProgramAddress([Buffer.from("collection_offer_vault"), collectionOffer.toBuffer()], program.programId);
+        return {collectionOffer, offerVault};
+    };
+
+    const makeCollectionOffer = async (collectionMintPubkey: PublicKey, price: number, maxItems: number) => {
+        const {collectionOffer, offerVault} = await offerPdas(collectionMintPubkey, buyerAccount.publicKey);
+        await program.rpc.makeCollectionOffer(
+            new anchor.BN(price),
+            maxItems,
+            new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+            {
+                accounts: {
+                    buyer: buyerAccount.publicKey,
+                    collectionMint: collectionMintPubkey,
+                    paymentMint: ftMintPubkey,
+                    buyerPaymentAccount: buyerFtTokenAccountPubkey,
+                    collectionOffer: collectionOffer,
+                    offerVault: offerVault,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                signers: [buyerAccount],
+            }
+        );
+        return {collectionOffer, offerVault};
+    };
+
+    const fillCollectionOffer = async (collectionOffer: PublicKey, offerVault: PublicKey, price: number) => {
+        return program.rpc.fillCollectionOffer(
+            new anchor.BN(price),
+            {
+                accounts: {
+                    seller: sellerAccount.publicKey,
+                    buyer: buyerAccount.publicKey,
+                    nftMint: COLLECTION_NFT.mint,
+                    nftMetadata: COLLECTION_NFT.metadata,
+                    sellerNftAccount: COLLECTION_NFT.tokenAccount,
+                    collectionOffer: collectionOffer,
+                    offerVault: offerVault,
+                    buyerNftAccount: await getAssociatedTokenAddress(COLLECTION_NFT.mint, buyerAccount.publicKey),
+                    royaltyConfig: royaltyConfigPubkey,
+                    sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                // recipient token accounts are passed in the order of the royalty config
+                remainingAccounts: [
+                    {pubkey: artistFtTokenAccountPubkey, isWritable: true, isSigner: false},
+                    {pubkey: platformFtTokenAccountPubkey, isWritable: true, isSigner: false},
+                ],
+                signers: [sellerAccount],
+            }
+        );
+    };
+
+    const cancelCollectionOffer = async (collectionMintPubkey: PublicKey, collectionOffer: PublicKey, offerVault: PublicKey) => {
+        return program.rpc.cancelCollectionOffer({
+            accounts: {
+                buyer: buyerAccount.publicKey,
+                collectionMint: collectionMintPubkey,
+                collectionOffer: collectionOffer,
+                offerVault: offerVault,
+                buyerPaymentAccount: buyerFtTokenAccountPubkey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [buyerAccount],
+        });
+    };
+
+    const closeFilledCollectionOffer = async (collectionOffer: PublicKey, offerVault: PublicKey) => {
+        return program.rpc.closeFilledCollectionOffer({
+            accounts: {
+                buyer: buyerAccount.publicKey,
+                collectionOffer: collectionOffer,
+                offerVault: offerVault,
+                buyerPaymentAccount: buyerFtTokenAccountPubkey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+        });
+    };
+
+    it("Rejects an NFT from another collection", async () => {
+        const otherCollectionMintPubkey = await createFtMint(connection, payerAccount);
+        const {collectionOffer, offerVault} = await makeCollectionOffer(otherCollectionMintPubkey, 100, 1);
+
+        await assert.rejects(
+            fillCollectionOffer(collectionOffer, offerVault, 100),
+            (e: any) => e.error?.errorCode?.code === "NotInCollection" || /NotInCollection/.test(e.toString())
+        );
+
+        await cancelCollectionOffer(otherCollectionMintPubkey, collectionOffer, offerVault);
+        assert.equal(await getTokenBalance(buyerFtTokenAccountPubkey, connection), 1000)
+    });
+
+    it("Escrows the deposit for every item and refunds what's left on cancel", async () => {
+        const {collectionOffer, offerVault} = await makeCollectionOffer(COLLECTION_MINT, 150, 2);
+        assert.equal(await getTokenBalance(offerVault, connection), 300)
+        assert.equal(await getTokenBalance(buyerFtTokenAccountPubkey, connection), 1000 - 300)
+
+        const signature = await cancelCollectionOffer(COLLECTION_MINT, collectionOffer, offerVault);
+        console.log(`cancel collection offer tx = ${signature}`)
+
+        assert.equal(await getTokenBalance(buyerFtTokenAccountPubkey, connection), 1000)
+        assert.equal(await connection.getAccountInfo(collectionOffer), null)
+        assert.equal(await connection.getAccountInfo(offerVault), null)
+    });
+
+    const price = 200
+    let collectionOfferPubkey: PublicKey
+    let offerVaultPubkey: PublicKey
+    it("Delivers a verified member to the buyer and splits the price with the royalty recipients", async () => {
+        ({collectionOffer: collectionOfferPubkey, offerVault: offerVaultPubkey} = await makeCollectionOffer(COLLECTION_MINT, price, 1));
+
+        // the offer can only be closed through cancel until it's filled
+        await assert.rejects(
+            closeFilledCollectionOffer(collectionOfferPubkey, offerVaultPubkey),
+            (e: any) => e.error?.errorCode?.code === "CollectionOfferNotFilled" || /CollectionOfferNotFilled/.test(e.toString())
+        );
+
+        const signature = await fillCollectionOffer(collectionOfferPubkey, offerVaultPubkey, price);
+        console.log(`fill collection offer tx = ${signature}`)
+
+        const buyerNftTokenAccountPubkey = await getAssociatedTokenAddress(COLLECTION_NFT.mint, buyerAccount.publicKey);
+        assert.equal(await getTokenBalance(COLLECTION_NFT.tokenAccount, connection), 0)
+        assert.equal(await getTokenBalance(buyerNftTokenAccountPubkey, connection), 1)
+        assert.equal(await getTokenBalance(artistFtTokenAccountPubkey, connection), 12)
+        assert.equal(await getTokenBalance(platformFtTokenAccountPubkey, connection), 8)
+        assert.equal(await getTokenBalance(sellerFtTokenAccountPubkey, connection), 180)
+        assert.equal(await getTokenBalance(offerVaultPubkey, connection), 0)
+
+        const collectionOffer = await program.account.collectionOffer.fetch(collectionOfferPubkey);
+        assert.equal(collectionOffer.filled, 1);
+    });
+
+    it("Closes the offer once its last item is bought", async () => {
+        // tokens sent to the vault directly go back to the buyer rather than blocking the close
+        await mintTo(connection, payerAccount, ftMintPubkey, offerVaultPubkey, payerAccount, 3, [], undefined, TOKEN_PROGRAM_ID);
+        const buyerFtBalance = await getTokenBalance(buyerFtTokenAccountPubkey, connection);
+
+        const buyerBalance = await connection.getBalance(buyerAccount.publicKey);
+        const signature = await closeFilledCollectionOffer(collectionOfferPubkey, offerVaultPubkey);
+        console.log(`close filled collection offer tx = ${signature}`)
+
+        assert.equal(await connection.getAccountInfo(collectionOfferPubkey), null)
+        assert.equal(await connection.getAccountInfo(offerVaultPubkey), null)
+        assert.ok(await connection.getBalance(buyerAccount.publicKey) > buyerBalance);
+        assert.equal(await getTokenBalance(buyerFtTokenAccountPubkey, connection), buyerFtBalance + 3)
+    });
+});
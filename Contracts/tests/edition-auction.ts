@@ -0,0 +1,330 @@
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
+import {AnchorAuction} from '../target/types/anchor_auction';
+import {SystemProgram, Connection, Keypair, PublicKey, SYSVAR_RENT_PUBKEY} from '@solana/web3.js';
+import {
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+    AuthorityType,
+    getAssociatedTokenAddress,
+    getMint,
+    mintTo,
+    setAuthority,
+    TOKEN_PROGRAM_ID
+} from "@solana/spl-token";
+import * as assert from "assert";
+import {airdrop, createFtMint, createTokenAccount, getTokenBalance} from './utils';
+
+describe('edition-auction', () => {
+    const connection = new Connection("http://localhost:8899", "confirmed");
+    const options = AnchorProvider.defaultOptions();
+    const wallet = NodeWallet.local();
+    const provider = new AnchorProvider(connection, wallet, options);
+
+    anchor.setProvider(provider);
+    const program = anchor.workspace.AnchorAuction as Program<AnchorAuction>;
+
+    const payerAccount = wallet.payer
+    const sellerAccount = Keypair.generate();
+    const bidderAccounts = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+
+    let ftMintPubkey: PublicKey
+    let sellerFtTokenAccountPubkey: PublicKey
+    const bidderFtTokenAccountPubkeys: PublicKey[] = []
+    it("Setup", async () => {
+        await airdrop(connection, [sellerAccount, ...bidderAccounts]);
+        ftMintPubkey = await createFtMint(connection, payerAccount);
+        sellerFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, sellerAccount.publicKey);
+        for (const bidder of bidderAccounts) {
+            const bidderFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, bidder.publicKey, 500);
+            bidderFtTokenAccountPubkeys.push(bidderFtTokenAccountPubkey);
+        }
+    });
+
+    const auctionPdas = async (editionMintPubkey: PublicKey, auctionId: number) => {
+        const [editionAuction] = await PublicKey.findProgramAddress(
+            [Buffer.from("edition_auction"), sellerAccount.publicKey.toBuffer(), editionMintPubkey.toBuffer(), new anchor.BN(auctionId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const [editionVault] = await PublicKey.findProgramAddress([Buffer.from("edition_vault"), editionAuction.toBuffer()], program.programId);
+        const [paymentVault] = await PublicKey.findProgramAddress([Buffer.from("edition_payment_vault"), editionAuction.toBuffer()], program.programId);
+        return {editionAuction, editionVault, paymentVault};
+    };
+
+    // every bidder places a single bid, so its index is always 0
+    const editionBidPda = async (editionAuction: PublicKey, bidder: Keypair) => {
+        const [editionBid] = await PublicKey.findProgramAddress(
+            [Buffer.from("edition_bid"), editionAuction.toBuffer(), bidder.publicKey.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, "le", 2)],
+            program.programId
+        );
+        return editionBid;
+    };
+
+    const placeBid = async (editionAuction: PublicKey, paymentVault: PublicKey, bidderIndex: number, quantity: number, price: number) => {
+        const bidder = bidderAccounts[bidderIndex];
+        const editionBid = await editionBidPda(editionAuction, bidder);
+        await program.rpc.placeEditionBid(
+            0,
+            quantity,
+            new anchor.BN(price),
+            {
+                accounts: {
+                    bidder: bidder.publicKey,
+                    editionAuction: editionAuction,
+                    editionBid: editionBid,
+                    bidderPaymentAccount: bidderFtTokenAccountPubkeys[bidderIndex],
+                    paymentVault: paymentVault,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                },
+                signers: [bidder],
+            }
+        );
+        return editionBid;
+    };
+
+    // the provider wallet pays for the edition accounts, anyone can push claims through
+    const claimBid = async (editionMintPubkey: PublicKey, pdas: {editionAuction: PublicKey, editionVault: PublicKey, paymentVault: PublicKey}, bidderIndex: number) => {
+        const bidder = bidderAccounts[bidderIndex];
+        const editionBid = await editionBidPda(pdas.editionAuction, bidder);
+        await program.rpc.claimEditionBid({
+            accounts: {
+                bidder: bidder.publicKey,
+                payer: wallet.publicKey,
+                editionAuction: pdas.editionAuction,
+                editionBid: editionBid,
+                editionMint: editionMintPubkey,
+                bidderEditionAccount: await getAssociatedTokenAddress(editionMintPubkey, bidder.publicKey),
+                bidderPaymentAccount: bidderFtTokenAccountPubkeys[bidderIndex],
+                editionVault: pdas.editionVault,
+                paymentVault: pdas.paymentVault,
+                tokenProgram: TOKEN_PROGRAM_ID,
+                associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                systemProgram: SystemProgram.programId,
+                rent: SYSVAR_RENT_PUBKEY,
+            },
+        });
+        return editionBid;
+    };
+
+    const waitForEnd = async (editionAuction: PublicKey) => {
+        const auction = await program.account.editionAuction.fetch(editionAuction);
+        await new Promise((resolve) => setTimeout(resolve, Math.max(0, auction.endAt.toNumber() * 1000 - Date.now()) + 2000));
+    };
+
+    describe('escrowed editions', () => {
+        const editionCount = 3
+        const reservePrice = 10
+        const duration = 10
+        let editionMintPubkey: PublicKey
+        let sellerEditionAccountPubkey: PublicKey
+        let pdas: {editionAuction: PublicKey, editionVault: PublicKey, paymentVault: PublicKey}
+        it("Escrows the editions on creation", async () => {
+            editionMintPubkey = await createFtMint(connection, payerAccount);
+            sellerEditionAccountPubkey = await createTokenAccount(connection, payerAccount, editionMintPubkey, sellerAccount.publicKey, editionCount);
+            pdas = await auctionPdas(editionMintPubkey, 0);
+
+            const signature = await program.rpc.createEscrowedEditionAuction(
+                new anchor.BN(0),
+                editionCount,
+                new anchor.BN(reservePrice),
+                new anchor.BN(duration),
+                {
+                    accounts: {
+                        create: {
+                            seller: sellerAccount.publicKey,
+                            editionMint: editionMintPubkey,
+                            paymentMint: ftMintPubkey,
+                            sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                            editionAuction: pdas.editionAuction,
+                            editionVault: pdas.editionVault,
+                            paymentVault: pdas.paymentVault,
+                            tokenProgram: TOKEN_PROGRAM_ID,
+                            systemProgram: SystemProgram.programId,
+                            rent: SYSVAR_RENT_PUBKEY,
+                        },
+                        sellerEditionAccount: sellerEditionAccountPubkey,
+                    },
+                    signers: [sellerAccount],
+                }
+            );
+            console.log(`create escrowed edition auction tx = ${signature}`)
+
+            assert.equal(await getTokenBalance(sellerEditionAccountPubkey, connection), 0)
+            assert.equal(await getTokenBalance(pdas.editionVault, connection), editionCount)
+        });
+
+        it("Escrows the full deposit of each bid", async () => {
+            await assert.rejects(
+                placeBid(pdas.editionAuction, pdas.paymentVault, 2, 1, reservePrice - 1),
+                (e: any) => e.error?.errorCode?.code === "BidBelowReserve" || /BidBelowReserve/.test(e.toString())
+            );
+
+            await placeBid(pdas.editionAuction, pdas.paymentVault, 0, 2, 30);
+            await placeBid(pdas.editionAuction, pdas.paymentVault, 1, 2, 20);
+            await placeBid(pdas.editionAuction, pdas.paymentVault, 2, 1, 15);
+
+            assert.equal(await getTokenBalance(pdas.paymentVault, connection), 60 + 40 + 15)
+            assert.equal(await getTokenBalance(bidderFtTokenAccountPubkeys[0], connection), 500 - 60)
+            assert.equal(await getTokenBalance(bidderFtTokenAccountPubkeys[1], connection), 500 - 40)
+            assert.equal(await getTokenBalance(bidderFtTokenAccountPubkeys[2], connection), 500 - 15)
+        });
+
+        it("Clears at the price of the lowest winning bid", async function () {
+            this.timeout(20000);
+            await assert.rejects(
+                program.rpc.clearEditionAuction({accounts: {editionAuction: pdas.editionAuction}}),
+                (e: any) => e.error?.errorCode?.code === "AuctionNotEnded" || /AuctionNotEnded/.test(e.toString())
+            );
+            await waitForEnd(pdas.editionAuction);
+
+            await program.rpc.clearEditionAuction({accounts: {editionAuction: pdas.editionAuction}});
+
+            const auction = await program.account.editionAuction.fetch(pdas.editionAuction);
+            assert.ok(auction.isCleared);
+            assert.equal(auction.clearingPrice.toNumber(), 20);
+            assert.equal(auction.unitsSold, editionCount);
+        });
+
+        it("Keeps the proceeds in escrow until every bid is claimed", async () => {
+            await assert.rejects(
+                program.rpc.withdrawEditionProceeds({
+                    accounts: {
+                        seller: sellerAccount.publicKey,
+                        editionAuction: pdas.editionAuction,
+                        sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                        sellerEditionAccount: sellerEditionAccountPubkey,
+                        editionMint: editionMintPubkey,
+                        editionVault: pdas.editionVault,
+                        paymentVault: pdas.paymentVault,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                    },
+                    signers: [sellerAccount],
+                }),
+                (e: any) => e.error?.errorCode?.code === "EditionClaimsPending" || /EditionClaimsPending/.test(e.toString())
+            );
+        });
+
+        it("Delivers won editions at the clearing price and refunds the rest", async () => {
+            const editionBids: PublicKey[] = [];
+            for (const bidderIndex of [0, 1, 2]) {
+                editionBids.push(await claimBid(editionMintPubkey, pdas, bidderIndex));
+            }
+
+            // 2 editions above the clearing price, 1 of 2 at it, none below it
+            const editionBalances = [2, 1, 0];
+            const refunds = [60 - 40, 40 - 20, 15];
+            for (const bidderIndex of [0, 1, 2]) {
+                const editionAccount = await getAssociatedTokenAddress(editionMintPubkey, bidderAccounts[bidderIndex].publicKey);
+                assert.equal(await getTokenBalance(editionAccount, connection), editionBalances[bidderIndex])
+                assert.equal(await getTokenBalance(bidderFtTokenAccountPubkeys[bidderIndex], connection), 500 - [60, 40, 15][bidderIndex] + refunds[bidderIndex])
+                assert.equal(await connection.getAccountInfo(editionBids[bidderIndex]), null)
+            }
+            assert.equal(await getTokenBalance(pdas.editionVault, connection), 0)
+            assert.equal(await getTokenBalance(pdas.paymentVault, connection), 60)
+        });
+
+        it("Pays the seller and closes the auction", async () => {
+            // tokens sent to a vault directly don't block the close, they go to the seller
+            await mintTo(connection, payerAccount, ftMintPubkey, pdas.paymentVault, payerAccount, 5, [], undefined, TOKEN_PROGRAM_ID);
+
+            const signature = await program.rpc.withdrawEditionProceeds({
+                accounts: {
+                    seller: sellerAccount.publicKey,
+                    editionAuction: pdas.editionAuction,
+                    sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                    sellerEditionAccount: sellerEditionAccountPubkey,
+                    editionMint: editionMintPubkey,
+                    editionVault: pdas.editionVault,
+                    paymentVault: pdas.paymentVault,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                signers: [sellerAccount],
+            });
+            console.log(`withdraw edition proceeds tx = ${signature}`)
+
+            assert.equal(await getTokenBalance(sellerFtTokenAccountPubkey, connection), 60 + 5)
+            assert.equal(await connection.getAccountInfo(pdas.editionAuction), null)
+            assert.equal(await connection.getAccountInfo(pdas.editionVault), null)
+            assert.equal(await connection.getAccountInfo(pdas.paymentVault), null)
+        });
+    });
+
+    describe('minted editions', () => {
+        const duration = 6
+        let editionMintPubkey: PublicKey
+        let sellerEditionAccountPubkey: PublicKey
+        let pdas: {editionAuction: PublicKey, editionVault: PublicKey, paymentVault: PublicKey}
+        it("Requires the auction to be the mint authority", async () => {
+            editionMintPubkey = await createFtMint(connection, payerAccount);
+            sellerEditionAccountPubkey = await createTokenAccount(connection, payerAccount, editionMintPubkey, sellerAccount.publicKey);
+            pdas = await auctionPdas(editionMintPubkey, 0);
+
+            const create = () => program.rpc.createEditionAuction(
+                new anchor.BN(0),
+                2,
+                new anchor.BN(10),
+                new anchor.BN(duration),
+                {
+                    accounts: {
+                        seller: sellerAccount.publicKey,
+                        editionMint: editionMintPubkey,
+                        paymentMint: ftMintPubkey,
+                        sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                        editionAuction: pdas.editionAuction,
+                        editionVault: pdas.editionVault,
+                        paymentVault: pdas.paymentVault,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                        rent: SYSVAR_RENT_PUBKEY,
+                    },
+                    signers: [sellerAccount],
+                }
+            );
+            await assert.rejects(
+                create(),
+                (e: any) => e.error?.errorCode?.code === "InvalidEditionAuction" || /InvalidEditionAuction/.test(e.toString())
+            );
+
+            await setAuthority(connection, payerAccount, editionMintPubkey, payerAccount, AuthorityType.MintTokens, pdas.editionAuction);
+            await create();
+        });
+
+        it("Mints the won editions on claim", async function () {
+            this.timeout(20000);
+            await placeBid(pdas.editionAuction, pdas.paymentVault, 0, 1, 25);
+            await waitForEnd(pdas.editionAuction);
+            await program.rpc.clearEditionAuction({accounts: {editionAuction: pdas.editionAuction}});
+
+            // a single bid for less than the supply clears at its own price
+            const auction = await program.account.editionAuction.fetch(pdas.editionAuction);
+            assert.equal(auction.clearingPrice.toNumber(), 25);
+            assert.equal(auction.unitsSold, 1);
+
+            const sellerBalance = await getTokenBalance(sellerFtTokenAccountPubkey, connection);
+            await claimBid(editionMintPubkey, pdas, 0);
+            const editionAccount = await getAssociatedTokenAddress(editionMintPubkey, bidderAccounts[0].publicKey);
+            assert.equal(await getTokenBalance(editionAccount, connection), 1)
+
+            await program.rpc.withdrawEditionProceeds({
+                accounts: {
+                    seller: sellerAccount.publicKey,
+                    editionAuction: pdas.editionAuction,
+                    sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                    sellerEditionAccount: sellerEditionAccountPubkey,
+                    editionMint: editionMintPubkey,
+                    editionVault: pdas.editionVault,
+                    paymentVault: pdas.paymentVault,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                signers: [sellerAccount],
+            });
+            assert.equal(await getTokenBalance(sellerFtTokenAccountPubkey, connection), sellerBalance + 25)
+            assert.equal(await getTokenBalance(sellerEditionAccountPubkey, connection), 0)
+
+            // the seller gets the mint back
+            const editionMint = await getMint(connection, editionMintPubkey);
+            assert.ok(editionMint.mintAuthority.equals(sellerAccount.publicKey));
+        });
+    });
+});
@@ -0,0 +1,240 @@
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
+import {AnchorAuction} from '../target/types/anchor_auction';
+import {SystemProgram, Connection, Keypair, PublicKey, SYSVAR_CLOCK_PUBKEY} from '@solana/web3.js';
+import {
+    getAccount,
+    transfer,
+    TOKEN_PROGRAM_ID
+} from "@solana/spl-token";
+import * as assert from "assert";
+import {accountInstructions, airdrop, createFtMint, createTokenAccount, getTokenBalance} from './utils';
+
+// Accounts below are loaded into the local validator from tests/fixtures, see Anchor.toml.
+// The mint's freeze authority is its master edition, so the Token Metadata program (loaded into the
+// validator) can freeze the seller's account on behalf of the escrow PDA.
+const METADATA_PROGRAM_ID = new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+const COLLECTION_MINT = new PublicKey("AoHdJiVrvHbQmUwqckLew17smdv1CxY5LRJaHyyWrt3R");
+const IN_PLACE_NFT = {
+    mint: new PublicKey("DYX528w286mekuNFENXijQCaaTqntRLQVC69Dixk6sGZ"),
+    tokenAccount: new PublicKey("BgyLSsQX2ejLHfvaN1o2VbjFs6NQgsKitmZmcwg4jS6h"),
+    metadata: new PublicKey("7cJccBWVXfn1HC5vqHEeNpYYE5PsSuNCHcETCUCojeDA"),
+    edition: new PublicKey("FJjEpHFTqvaVD4PdBMrTrub6dRsAdSkGXqroSnSkDdxd"),
+};
+
+describe('in-place-listing', () => {
+    const connection = new Connection("http://localhost:8899", "confirmed");
+    const options = AnchorProvider.defaultOptions();
+    const wallet = NodeWallet.local();
+    const provider = new AnchorProvider(connection, wallet, options);
+
+    anchor.setProvider(provider);
+    const program = anchor.workspace.AnchorAuction as Program<AnchorAuction>;
+
+    const payerAccount = wallet.payer
+    const sellerAccount = Keypair.fromSecretKey(Uint8Array.from(require("./fixtures/seller.json")));
+    const bidderAccount = Keypair.generate();
+    const trackId = `in-place-${Date.now()}`;
+
+    let ftMintPubkey: PublicKey
+    let sellerFtTokenAccountPubkey: PublicKey
+    let bidderFtTokenAccountPubkey: PublicKey
+    let musicTrackPubkey: PublicKey
+    let pdaPubkey: PublicKey
+    it("Setup", async () => {
+        await airdrop(connection, [sellerAccount, bidderAccount]);
+        ftMintPubkey = await createFtMint(connection, payerAccount);
+        sellerFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, sellerAccount.publicKey);
+        bidderFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, bidderAccount.publicKey, 500);
+
+        [pdaPubkey] = await PublicKey.findProgramAddress([Buffer.from("escrow")], program.programId);
+
+        // a track gated on the NFT's collection, to check the seller keeps playback while listed
+        const [musicTrack] = await PublicKey.findProgramAddress([Buffer.from("music_track"), wallet.publicKey.toBuffer(), Buffer.from(trackId)], program.programId);
+        const [authRegistry] = await PublicKey.findProgramAddress([Buffer.from("auth_registry")], program.programId);
+        musicTrackPubkey = musicTrack;
+        await program.rpc.registerMusicTrack(
+            trackId,
+            "ipfs://track",
+            "ipfs://preview",
+            "ipfs://metadata",
+            false,
+            {
+                accounts: {
+                    authority: wallet.publicKey,
+                    musicTrack: musicTrackPubkey,
+                    authRegistry: authRegistry,
+                    systemProgram: SystemProgram.programId,
+                },
+            }
+        );
+        await program.rpc.addAuthorizedCollection(
+            COLLECTION_MINT,
+            {
+                accounts: {
+                    authority: wallet.publicKey,
+                    musicTrack: musicTrackPubkey,
+                },
+            }
+        );
+    });
+
+    const exhibitInPlace = async (escrowAccount: Keypair, duration: number) => {
+        return program.rpc.exhibitInPlace(
+            new anchor.BN(100),
+            new anchor.BN(duration),
+            {
+                accounts: {
+                    exhibitor: sellerAccount.publicKey,
+                    exhibitorNftTokenAccount: IN_PLACE_NFT.tokenAccount,
+                    nftMint: IN_PLACE_NFT.mint,
+                    nftEdition: IN_PLACE_NFT.edition,
+                    exhibitorFtReceivingAccount: sellerFtTokenAccountPubkey,
+                    escrowAccount: escrowAccount.publicKey,
+                    musicTrack: null,
+                    clock: SYSVAR_CLOCK_PUBKEY,
+                    pda: pdaPubkey,
+                    metadataProgram: METADATA_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                instructions: [
+                    await program.account.auction.createInstruction(escrowAccount),
+                ],
+                signers: [sellerAccount, escrowAccount]
+            }
+        );
+    };
+
+    const cancelledEscrowAccount = Keypair.generate();
+    it("Freezes the NFT in the seller's account", async () => {
+        const signature = await exhibitInPlace(cancelledEscrowAccount, 60);
+        console.log(`exhibit in place tx = ${signature}`)
+
+        const tokenAccount = await getAccount(connection, IN_PLACE_NFT.tokenAccount);
+        assert.ok(tokenAccount.isFrozen);
+        assert.ok(tokenAccount.delegate.equals(pdaPubkey));
+        assert.ok(tokenAccount.owner.equals(sellerAccount.publicKey));
+        assert.equal(Number(tokenAccount.amount), 1);
+    });
+
+    it("Keeps the NFT from being moved while listed", async () => {
+        const otherTokenAccountPubkey = await createTokenAccount(connection, payerAccount, IN_PLACE_NFT.mint, bidderAccount.publicKey);
+        await assert.rejects(
+            transfer(connection, payerAccount, IN_PLACE_NFT.tokenAccount, otherTokenAccountPubkey, sellerAccount, 1, [], undefined, TOKEN_PROGRAM_ID)
+        );
+        assert.equal(await getTokenBalance(IN_PLACE_NFT.tokenAccount, connection), 1)
+    });
+
+    it("Keeps playback working for the seller while listed", async () => {
+        await program.rpc.verifyPlaybackAuthorization({
+            accounts: {
+                user: sellerAccount.publicKey,
+                authority: sellerAccount.publicKey,
+                session: null,
+                musicTrack: musicTrackPubkey,
+                userTokenAccount: IN_PLACE_NFT.tokenAccount,
+                nftMetadata: IN_PLACE_NFT.metadata,
+                metadataProgram: METADATA_PROGRAM_ID,
+                accessPass: null,
+                subscription: null,
+                delegation: null,
+                rental: null,
+                userAuth: null,
+                systemProgram: SystemProgram.programId,
+                clock: SYSVAR_CLOCK_PUBKEY,
+            },
+            signers: [sellerAccount],
+        });
+    });
+
+    it("Thaws the NFT and drops the delegation on cancel", async () => {
+        const signature = await program.rpc.cancel({
+            accounts: {
+                exhibitor: sellerAccount.publicKey,
+                exhibitorNftTokenAccount: IN_PLACE_NFT.tokenAccount,
+                exhibitorNftTempAccount: IN_PLACE_NFT.tokenAccount,
+                escrowAccount: cancelledEscrowAccount.publicKey,
+                nftMint: IN_PLACE_NFT.mint,
+                nftEdition: IN_PLACE_NFT.edition,
+                metadataProgram: METADATA_PROGRAM_ID,
+                pda: pdaPubkey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            signers: [sellerAccount],
+        });
+        console.log(`cancel tx = ${signature}`)
+
+        const tokenAccount = await getAccount(connection, IN_PLACE_NFT.tokenAccount);
+        assert.ok(!tokenAccount.isFrozen);
+        assert.equal(tokenAccount.delegate, null);
+        assert.equal(Number(tokenAccount.amount), 1);
+        assert.equal(await connection.getAccountInfo(cancelledEscrowAccount.publicKey), null)
+    });
+
+    const escrowAccount = Keypair.generate();
+    const duration = 8
+    it("Delivers the NFT straight from the seller's account to the winner", async function () {
+        this.timeout(30000);
+        await exhibitInPlace(escrowAccount, duration);
+
+        const bidderFtTempAccount = Keypair.generate();
+        let auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        await program.rpc.bid(
+            new anchor.BN(150),
+            {
+                accounts: {
+                    bidder: bidderAccount.publicKey,
+                    bidderFtTempAccount: bidderFtTempAccount.publicKey,
+                    bidderFtAccount: bidderFtTokenAccountPubkey,
+                    highestBidder: auction.highestBidderPubkey,
+                    highestBidderFtTempAccount: auction.highestBidderFtTempPubkey,
+                    highestBidderFtReturningAccount: auction.highestBidderFtReturningPubkey,
+                    escrowAccount: escrowAccount.publicKey,
+                    clock: SYSVAR_CLOCK_PUBKEY,
+                    pda: pdaPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                instructions: [
+                    ...await accountInstructions(connection, ftMintPubkey, bidderFtTempAccount.publicKey, bidderAccount.publicKey),
+                ],
+                signers: [bidderAccount, bidderFtTempAccount]
+            }
+        );
+
+        // sleep until the auction has ended
+        auction = await program.account.auction.fetch(escrowAccount.publicKey)
+        await new Promise((resolve) => setTimeout(resolve, Math.max(0, auction.endAt.toNumber() * 1000 - Date.now()) + 2000));
+
+        const bidderNftReceivingAccount = Keypair.generate();
+        const signature = await program.rpc.close({
+            accounts: {
+                winningBidder: bidderAccount.publicKey,
+                exhibitor: sellerAccount.publicKey,
+                exhibitorNftTempAccount: IN_PLACE_NFT.tokenAccount,
+                exhibitorFtReceivingAccount: sellerFtTokenAccountPubkey,
+                highestBidderFtTempAccount: bidderFtTempAccount.publicKey,
+                highestBidderNftReceivingAccount: bidderNftReceivingAccount.publicKey,
+                escrowAccount: escrowAccount.publicKey,
+                nftMint: IN_PLACE_NFT.mint,
+                nftEdition: IN_PLACE_NFT.edition,
+                metadataProgram: METADATA_PROGRAM_ID,
+                clock: SYSVAR_CLOCK_PUBKEY,
+                pda: pdaPubkey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            },
+            instructions: [
+                ...await accountInstructions(connection, IN_PLACE_NFT.mint, bidderNftReceivingAccount.publicKey, bidderAccount.publicKey),
+            ],
+            signers: [bidderAccount, bidderNftReceivingAccount],
+        });
+        console.log(`close tx = ${signature}`)
+
+        const sellerTokenAccount = await getAccount(connection, IN_PLACE_NFT.tokenAccount);
+        assert.ok(!sellerTokenAccount.isFrozen);
+        assert.equal(Number(sellerTokenAccount.amount), 0);
+        assert.equal(await getTokenBalance(bidderNftReceivingAccount.publicKey, connection), 1)
+        assert.equal(await getTokenBalance(sellerFtTokenAccountPubkey, connection), 150)
+        assert.equal(await getTokenBalance(bidderFtTokenAccountPubkey, connection), 500 - 150)
+    });
+});
@@ -0,0 +1,285 @@
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
+import {AnchorAuction} from '../target/types/anchor_auction';
+import {SystemProgram, Connection, Keypair, PublicKey, SYSVAR_RENT_PUBKEY} from '@solana/web3.js';
+import {
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+    getAssociatedTokenAddress,
+    mintTo,
+    TOKEN_PROGRAM_ID
+} from "@solana/spl-token";
+import * as assert from "assert";
+import {airdrop, createFtMint, createNft, createTokenAccount, getTokenBalance} from './utils';
+
+// Royalties are 10% of the price, split 6% to the artist and 4% to the platform
+const ARTIST_BASIS_POINTS = 600;
+const PLATFORM_BASIS_POINTS = 400;
+
+describe('marketplace', () => {
+    const connection = new Connection("http://localhost:8899", "confirmed");
+    const options = AnchorProvider.defaultOptions();
+    const wallet = NodeWallet.local();
+    const provider = new AnchorProvider(connection, wallet, options);
+
+    anchor.setProvider(provider);
+    const program = anchor.workspace.AnchorAuction as Program<AnchorAuction>;
+
+    const payerAccount = wallet.payer
+    const sellerAccount = Keypair.generate();
+    const buyerAccount = Keypair.generate();
+    const offererAccount = Keypair.generate();
+    const artistAccount = Keypair.generate();
+    const platformAccount = Keypair.generate();
+
+    let nftMintPubkey: PublicKey
+    let ftMintPubkey: PublicKey
+    let sellerNftTokenAccountPubkey: PublicKey
+    let sellerFtTokenAccountPubkey: PublicKey
+    let buyerFtTokenAccountPubkey: PublicKey
+    let offererFtTokenAccountPubkey: PublicKey
+    let artistFtTokenAccountPubkey: PublicKey
+    let platformFtTokenAccountPubkey: PublicKey
+    let royaltyConfigPubkey: PublicKey
+    it("Setup", async () => {
+        await airdrop(connection, [sellerAccount, buyerAccount, offererAccount]);
+        ({mint: nftMintPubkey, tokenAccount: sellerNftTokenAccountPubkey} = await createNft(connection, payerAccount, sellerAccount.publicKey));
+
+        ftMintPubkey = await createFtMint(connection, payerAccount);
+        sellerFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, sellerAccount.publicKey);
+        buyerFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, buyerAccount.publicKey, 2000);
+        offererFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, offererAccount.publicKey, 2000);
+        artistFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, artistAccount.publicKey);
+        platformFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, platformAccount.publicKey);
+
+        const [royaltyConfig] = await PublicKey.findProgramAddress([Buffer.from("royalty_config"), nftMintPubkey.toBuffer()], program.programId);
+        royaltyConfigPubkey = royaltyConfig;
+        await program.rpc.createRoyaltyConfig(
+            ARTIST_BASIS_POINTS + PLATFORM_BASIS_POINTS,
+            [
+                {recipient: artistAccount.publicKey, basisPoints: ARTIST_BASIS_POINTS, recipientType: {artist: {}}},
+                {recipient: platformAccount.publicKey, basisPoints: PLATFORM_BASIS_POINTS, recipientType: {platform: {}}},
+            ],
+            false,
+            {
+                accounts: {
+                    authority: sellerAccount.publicKey,
+                    nftMint: nftMintPubkey,
+                    nftTokenAccount: sellerNftTokenAccountPubkey,
+                    royaltyConfig: royaltyConfigPubkey,
+                    systemProgram: SystemProgram.programId,
+                },
+                signers: [sellerAccount],
+            }
+        );
+    });
+
+    // recipient token accounts are passed in the order of the royalty config
+    const royaltyAccounts = () => [
+        {pubkey: artistFtTokenAccountPubkey, isWritable: true, isSigner: false},
+        {pubkey: platformFtTokenAccountPubkey, isWritable: true, isSigner: false},
+    ];
+
+    const price = 1000
+    let listingPubkey: PublicKey
+    let listingVaultPubkey: PublicKey
+    it("Escrows a listed NFT", async () => {
+        [listingPubkey] = await PublicKey.findProgramAddress([Buffer.from("listing"), nftMintPubkey.toBuffer()], program.programId);
+        [listingVaultPubkey] = await PublicKey.findProgramAddress([Buffer.from("listing_vault"), nftMintPubkey.toBuffer()], program.programId);
+        const signature = await program.rpc.list(
+            new anchor.BN(price),
+            {
+                accounts: {
+                    seller: sellerAccount.publicKey,
+                    nftMint: nftMintPubkey,
+                    sellerNftAccount: sellerNftTokenAccountPubkey,
+                    paymentMint: ftMintPubkey,
+                    sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                    listing: listingPubkey,
+                    listingVault: listingVaultPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                signers: [sellerAccount],
+            }
+        );
+        console.log(`list tx = ${signature}`)
+
+        const listing = await program.account.listing.fetch(listingPubkey);
+        assert.ok(listing.seller.equals(sellerAccount.publicKey));
+        assert.equal(listing.price.toNumber(), price);
+        assert.equal(await getTokenBalance(sellerNftTokenAccountPubkey, connection), 0)
+        assert.equal(await getTokenBalance(listingVaultPubkey, connection), 1)
+    });
+
+    const buy = async (buyPrice: number) => {
+        return program.rpc.buy(
+            new anchor.BN(buyPrice),
+            {
+                accounts: {
+                    buyer: buyerAccount.publicKey,
+                    seller: sellerAccount.publicKey,
+                    nftMint: nftMintPubkey,
+                    listing: listingPubkey,
+                    listingVault: listingVaultPubkey,
+                    buyerNftAccount: await getAssociatedTokenAddress(nftMintPubkey, buyerAccount.publicKey),
+                    royaltyConfig: royaltyConfigPubkey,
+                    buyerPaymentAccount: buyerFtTokenAccountPubkey,
+                    sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                remainingAccounts: royaltyAccounts(),
+                signers: [buyerAccount],
+            }
+        );
+    };
+
+    it("Rejects a buy at a price other than the listing's", async () => {
+        await assert.rejects(
+            buy(price - 1),
+            (e: any) => e.error?.errorCode?.code === "InvalidListing" || /InvalidListing/.test(e.toString())
+        );
+    });
+
+    it("Rejects a buy that leaves out the royalty recipients", async () => {
+        await assert.rejects(
+            program.rpc.buy(
+                new anchor.BN(price),
+                {
+                    accounts: {
+                        buyer: buyerAccount.publicKey,
+                        seller: sellerAccount.publicKey,
+                        nftMint: nftMintPubkey,
+                        listing: listingPubkey,
+                        listingVault: listingVaultPubkey,
+                        buyerNftAccount: await getAssociatedTokenAddress(nftMintPubkey, buyerAccount.publicKey),
+                        royaltyConfig: royaltyConfigPubkey,
+                        buyerPaymentAccount: buyerFtTokenAccountPubkey,
+                        sellerPaymentAccount: sellerFtTokenAccountPubkey,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                        rent: SYSVAR_RENT_PUBKEY,
+                    },
+                    signers: [buyerAccount],
+                }
+            ),
+            (e: any) => e.error?.errorCode?.code === "MissingExpectedAccount" || /MissingExpectedAccount/.test(e.toString())
+        );
+    });
+
+    it("Delivers the NFT to the buyer and splits the price with the royalty recipients", async () => {
+        const signature = await buy(price);
+        console.log(`buy tx = ${signature}`)
+
+        const buyerNftTokenAccountPubkey = await getAssociatedTokenAddress(nftMintPubkey, buyerAccount.publicKey);
+        assert.equal(await getTokenBalance(buyerNftTokenAccountPubkey, connection), 1)
+        assert.equal(await getTokenBalance(buyerFtTokenAccountPubkey, connection), 2000 - price)
+        assert.equal(await getTokenBalance(artistFtTokenAccountPubkey, connection), 60)
+        assert.equal(await getTokenBalance(platformFtTokenAccountPubkey, connection), 40)
+        assert.equal(await getTokenBalance(sellerFtTokenAccountPubkey, connection), 900)
+        assert.equal(await connection.getAccountInfo(listingPubkey), null)
+        assert.equal(await connection.getAccountInfo(listingVaultPubkey), null)
+    });
+
+    const offerPrice = 500
+    let offerPubkey: PublicKey
+    let offerVaultPubkey: PublicKey
+    const makeOffer = async () => {
+        [offerPubkey] = await PublicKey.findProgramAddress(
+            [Buffer.from("offer"), nftMintPubkey.toBuffer(), offererAccount.publicKey.toBuffer()],
+            program.programId
+        );
+        [offerVaultPubkey] = await PublicKey.findProgramAddress([Buffer.from("offer_vault"), offerPubkey.toBuffer()], program.programId);
+        return program.rpc.makeOffer(
+            new anchor.BN(offerPrice),
+            new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+            {
+                accounts: {
+                    buyer: offererAccount.publicKey,
+                    nftMint: nftMintPubkey,
+                    paymentMint: ftMintPubkey,
+                    buyerPaymentAccount: offererFtTokenAccountPubkey,
+                    offer: offerPubkey,
+                    offerVault: offerVaultPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                signers: [offererAccount],
+            }
+        );
+    };
+
+    it("Escrows an offer and refunds it in full on cancel", async () => {
+        await makeOffer();
+        assert.equal(await getTokenBalance(offerVaultPubkey, connection), offerPrice)
+        assert.equal(await getTokenBalance(offererFtTokenAccountPubkey, connection), 2000 - offerPrice)
+
+        const signature = await program.rpc.cancelOffer(
+            {
+                accounts: {
+                    buyer: offererAccount.publicKey,
+                    nftMint: nftMintPubkey,
+                    offer: offerPubkey,
+                    offerVault: offerVaultPubkey,
+                    buyerPaymentAccount: offererFtTokenAccountPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                signers: [offererAccount],
+            }
+        );
+        console.log(`cancel offer tx = ${signature}`)
+
+        assert.equal(await getTokenBalance(offererFtTokenAccountPubkey, connection), 2000)
+        assert.equal(await connection.getAccountInfo(offerPubkey), null)
+        assert.equal(await connection.getAccountInfo(offerVaultPubkey), null)
+    });
+
+    it("Delivers the NFT to the offerer and splits the offer with the royalty recipients", async () => {
+        await makeOffer();
+        // tokens sent to the vault directly go back to the offerer rather than blocking the close
+        await mintTo(connection, payerAccount, ftMintPubkey, offerVaultPubkey, payerAccount, 7, [], undefined, TOKEN_PROGRAM_ID);
+
+        // the buyer of the listing now holds the NFT and accepts
+        const ownerNftTokenAccountPubkey = await getAssociatedTokenAddress(nftMintPubkey, buyerAccount.publicKey);
+        const offererNftTokenAccountPubkey = await getAssociatedTokenAddress(nftMintPubkey, offererAccount.publicKey);
+        const signature = await program.rpc.acceptOffer(
+            new anchor.BN(offerPrice),
+            {
+                accounts: {
+                    seller: buyerAccount.publicKey,
+                    buyer: offererAccount.publicKey,
+                    nftMint: nftMintPubkey,
+                    sellerNftAccount: ownerNftTokenAccountPubkey,
+                    offer: offerPubkey,
+                    offerVault: offerVaultPubkey,
+                    buyerNftAccount: offererNftTokenAccountPubkey,
+                    royaltyConfig: royaltyConfigPubkey,
+                    sellerPaymentAccount: buyerFtTokenAccountPubkey,
+                    buyerPaymentAccount: offererFtTokenAccountPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                remainingAccounts: royaltyAccounts(),
+                signers: [buyerAccount],
+            }
+        );
+        console.log(`accept offer tx = ${signature}`)
+
+        assert.equal(await getTokenBalance(ownerNftTokenAccountPubkey, connection), 0)
+        assert.equal(await getTokenBalance(offererNftTokenAccountPubkey, connection), 1)
+        assert.equal(await getTokenBalance(offererFtTokenAccountPubkey, connection), 2000 - offerPrice + 7)
+        assert.equal(await getTokenBalance(artistFtTokenAccountPubkey, connection), 60 + 30)
+        assert.equal(await getTokenBalance(platformFtTokenAccountPubkey, connection), 40 + 20)
+        assert.equal(await getTokenBalance(buyerFtTokenAccountPubkey, connection), 2000 - price + 450)
+        assert.equal(await connection.getAccountInfo(offerPubkey), null)
+        assert.equal(await connection.getAccountInfo(offerVaultPubkey), null)
+    });
+});
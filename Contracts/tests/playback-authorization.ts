@@ -0,0 +1,247 @@
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
+import {AnchorAuction} from '../target/types/anchor_auction';
+import {SystemProgram, Connection, Keypair, LAMPORTS_PER_SOL, PublicKey, SYSVAR_CLOCK_PUBKEY} from '@solana/web3.js';
+import * as assert from "assert";
+
+// Accounts below are loaded into the local validator from tests/fixtures, see Anchor.toml.
+// Both NFTs carry real Token Metadata accounts pointing at the same collection, only one of them verified.
+const METADATA_PROGRAM_ID = new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+const COLLECTION_PUBKEY = new PublicKey("GKQvNtFy4AohJ731qcfdfNvtUMG1WHXrr5r26iHRY84e");
+const VERIFIED_NFT = {
+    mint: new PublicKey("7yzU4egT5nYRqW8umpf3s4MeHoCmC2JaJoame2E9rzLS"),
+    tokenAccount: new PublicKey("47U96PZBJvkrd5AjHTgsfNhdcqjU7dXT9zhRerz1HKAb"),
+    metadata: new PublicKey("3pJcBiZUh6eDf4fPZL6yT64Q84TvPdStYCmstyWd5QNs"),
+};
+const UNVERIFIED_NFT = {
+    mint: new PublicKey("4UWmddpjhxgktosqKKjeFG5hsDcTqooVeubJgerL7vx4"),
+    tokenAccount: new PublicKey("FAK7tPQmTkYUZUPDq6YVCjJXwwzHR9uTfHGwUfDmZGZh"),
+    metadata: new PublicKey("8PHGoNzWKEfkoShdxeJNBt2GjTmF3z4mriG13ajnj7zg"),
+};
+
+describe('playback-authorization', () => {
+    const connection = new Connection("http://localhost:8899", "confirmed");
+    const options = AnchorProvider.defaultOptions();
+    const wallet = NodeWallet.local();
+    const provider = new AnchorProvider(connection, wallet, options);
+
+    anchor.setProvider(provider);
+    const program = anchor.workspace.AnchorAuction as Program<AnchorAuction>;
+
+    const listenerAccount = Keypair.fromSecretKey(Uint8Array.from(require("./fixtures/listener.json")));
+    const trackId = `gated-${Date.now()}`;
+
+    let musicTrackPubkey: PublicKey
+    it("Setup", async () => {
+        const signature = await connection.requestAirdrop(listenerAccount.publicKey, LAMPORTS_PER_SOL);
+        await connection.confirmTransaction(signature);
+
+        // the fixtures must agree with the PDA the program derives
+        for (const nft of [VERIFIED_NFT, UNVERIFIED_NFT]) {
+            const [metadataPubkey] = await PublicKey.findProgramAddress(
+                [Buffer.from("metadata"), METADATA_PROGRAM_ID.toBuffer(), nft.mint.toBuffer()],
+                METADATA_PROGRAM_ID
+            );
+            assert.ok(metadataPubkey.equals(nft.metadata));
+        }
+
+        const [musicTrack] = await PublicKey.findProgramAddress([Buffer.from("music_track"), wallet.publicKey.toBuffer(), Buffer.from(trackId)], program.programId);
+        const [authRegistry] = await PublicKey.findProgramAddress([Buffer.from("auth_registry")], program.programId);
+        musicTrackPubkey = musicTrack;
+
+        await program.rpc.registerMusicTrack(
+            trackId,
+            "ipfs://track",
+            "ipfs://preview",
+            "ipfs://metadata",
+            false,
+            {
+                accounts: {
+                    authority: wallet.publicKey,
+                    musicTrack: musicTrackPubkey,
+                    authRegistry: authRegistry,
+                    systemProgram: SystemProgram.programId,
+                },
+            }
+        );
+        await program.rpc.addAuthorizedCollection(
+            COLLECTION_PUBKEY,
+            {
+                accounts: {
+                    authority: wallet.publicKey,
+                    musicTrack: musicTrackPubkey,
+                },
+            }
+        );
+
+        const track = await program.account.musicTrack.fetch(musicTrackPubkey);
+        assert.equal(track.authorizedCollections.length, 1);
+        assert.ok(track.authorizedCollections[0].equals(COLLECTION_PUBKEY));
+    });
+
+    const playbackContext = (
+        nft: {tokenAccount: PublicKey, metadata: PublicKey},
+        userAuth: PublicKey | null = null,
+        sessionKey: Keypair | null = null,
+        session: PublicKey | null = null
+    ) => {
+        return {
+            accounts: {
+                user: listenerAccount.publicKey,
+                authority: (sessionKey ?? listenerAccount).publicKey,
+                session: session,
+                musicTrack: musicTrackPubkey,
+                userTokenAccount: nft.tokenAccount,
+                nftMetadata: nft.metadata,
+                metadataProgram: METADATA_PROGRAM_ID,
+                accessPass: null,
+                subscription: null,
+                delegation: null,
+                rental: null,
+                userAuth: userAuth,
+                systemProgram: SystemProgram.programId,
+                clock: SYSVAR_CLOCK_PUBKEY,
+            },
+            signers: [sessionKey ?? listenerAccount],
+        };
+    };
+    const verify = async (nft: {tokenAccount: PublicKey, metadata: PublicKey}, userAuth: PublicKey | null = null) => {
+        return program.rpc.verifyPlaybackAuthorization(playbackContext(nft, userAuth));
+    };
+    // check_playback_authorization never fails on a denial, the outcome comes back as return data
+    const check = async (nft: {tokenAccount: PublicKey, metadata: PublicKey}) => {
+        const {raw} = await program.simulate.checkPlaybackAuthorization(playbackContext(nft));
+        const prefix = `Program return: ${program.programId.toBase58()} `;
+        const returnLog = raw.find((log) => log.startsWith(prefix));
+        return program.coder.types.decode("PlaybackAuthorization", Buffer.from(returnLog.slice(prefix.length), "base64"));
+    };
+
+    it("Grants playback to an NFT in a verified, authorized collection", async () => {
+        const signature = await verify(VERIFIED_NFT);
+        console.log(`Verified ${signature}`);
+    });
+
+    it("Rejects an NFT whose collection is not verified", async () => {
+        await assert.rejects(
+            verify(UNVERIFIED_NFT),
+            (e: any) => e.error?.errorCode?.code === "UnauthorizedPlayback" || /UnauthorizedPlayback/.test(e.toString())
+        );
+    });
+
+    it("Rejects metadata that doesn't belong to the token's mint", async () => {
+        await assert.rejects(
+            verify({tokenAccount: UNVERIFIED_NFT.tokenAccount, metadata: VERIFIED_NFT.metadata}),
+            (e: any) => e.error?.errorCode?.code === "InvalidNFTMetadata" || /InvalidNFTMetadata/.test(e.toString())
+        );
+    });
+
+    it("Returns the grant and its reason to callers", async () => {
+        const granted = await check(VERIFIED_NFT);
+        assert.ok(granted.granted);
+        assert.equal(granted.tier, 0);
+        assert.ok("collection" in granted.reason);
+
+        const denied = await check(UNVERIFIED_NFT);
+        assert.ok(!denied.granted);
+        assert.ok("denied" in denied.reason);
+    });
+
+    it("Lets a session key verify playback for the listener", async () => {
+        const sessionKey = Keypair.generate();
+        const [session] = await PublicKey.findProgramAddress(
+            [Buffer.from("session_key"), listenerAccount.publicKey.toBuffer(), sessionKey.publicKey.toBuffer()],
+            program.programId
+        );
+        await program.rpc.createSessionKey(
+            [musicTrackPubkey],
+            [],
+            new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+            new anchor.BN(0),
+            {
+                accounts: {
+                    user: listenerAccount.publicKey,
+                    sessionKey: sessionKey.publicKey,
+                    session: session,
+                    systemProgram: SystemProgram.programId,
+                },
+                signers: [listenerAccount],
+            }
+        );
+
+        await program.rpc.verifyPlaybackAuthorization(playbackContext(VERIFIED_NFT, null, sessionKey, session));
+
+        // without the session the key is just a stranger
+        await assert.rejects(
+            program.rpc.verifyPlaybackAuthorization(playbackContext(VERIFIED_NFT, null, sessionKey)),
+            (e: any) => e.error?.errorCode?.code === "InvalidSessionKey" || /InvalidSessionKey/.test(e.toString())
+        );
+    });
+
+    describe('authorization cache', () => {
+        let userAuthPubkey: PublicKey
+        it("Re-verifies and stamps a freshly created cache", async () => {
+            const [userAuth] = await PublicKey.findProgramAddress(
+                [Buffer.from("user_auth"), listenerAccount.publicKey.toBuffer(), musicTrackPubkey.toBuffer()],
+                program.programId
+            );
+            userAuthPubkey = userAuth;
+            // the provider wallet stands in for the relayer paying the rent
+            const listenerBalance = await connection.getBalance(listenerAccount.publicKey);
+            await program.rpc.createUserAuthCache(
+                trackId,
+                {
+                    accounts: {
+                        user: listenerAccount.publicKey,
+                        payer: wallet.publicKey,
+                        musicTrack: musicTrackPubkey,
+                        userAuth: userAuthPubkey,
+                        systemProgram: SystemProgram.programId,
+                    },
+                    signers: [listenerAccount],
+                }
+            );
+            const created = await program.account.userAuthCache.fetch(userAuthPubkey);
+            assert.equal(created.version, 1);
+            assert.equal(created.expiresAt.toNumber(), 0);
+            assert.ok(created.payer.equals(wallet.publicKey));
+            assert.equal(await connection.getBalance(listenerAccount.publicKey), listenerBalance);
+
+            await verify(VERIFIED_NFT, userAuthPubkey);
+
+            const cache = await program.account.userAuthCache.fetch(userAuthPubkey);
+            assert.ok(cache.expiresAt.toNumber() > Date.now() / 1000);
+            assert.ok(cache.grantedVia.equals(COLLECTION_PUBKEY));
+            assert.equal(cache.tier, 0);
+        });
+
+        it("Re-verifies and re-stamps an expired cache", async () => {
+            await program.rpc.setTrackAuthTtl(
+                new anchor.BN(1),
+                {
+                    accounts: {
+                        authority: wallet.publicKey,
+                        musicTrack: musicTrackPubkey,
+                    },
+                }
+            );
+            await verify(VERIFIED_NFT, userAuthPubkey);
+            const stamped = (await program.account.userAuthCache.fetch(userAuthPubkey)).expiresAt.toNumber();
+
+            // sleep until the 1 second TTL has passed
+            await new Promise((resolve) => setTimeout(resolve, 3000));
+
+            await verify(VERIFIED_NFT, userAuthPubkey);
+            const restamped = (await program.account.userAuthCache.fetch(userAuthPubkey)).expiresAt.toNumber();
+            assert.ok(restamped > stamped);
+        });
+
+        it("Rejects an expired cache once the holder no longer qualifies", async () => {
+            await new Promise((resolve) => setTimeout(resolve, 3000));
+            await assert.rejects(
+                verify(UNVERIFIED_NFT, userAuthPubkey),
+                (e: any) => e.error?.errorCode?.code === "UnauthorizedPlayback" || /UnauthorizedPlayback/.test(e.toString())
+            );
+        });
+    });
+});
@@ -0,0 +1,230 @@
+import * as anchor from '@coral-xyz/anchor';
+import {AnchorProvider, Program} from '@coral-xyz/anchor';
+import NodeWallet from '@coral-xyz/anchor/dist/cjs/nodewallet';
+import {AnchorAuction} from '../target/types/anchor_auction';
+import {SystemProgram, Connection, Keypair, PublicKey, SYSVAR_CLOCK_PUBKEY, SYSVAR_RENT_PUBKEY} from '@solana/web3.js';
+import {
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+    getAccount,
+    getAssociatedTokenAddress,
+    transfer,
+    TOKEN_PROGRAM_ID
+} from "@solana/spl-token";
+import * as assert from "assert";
+import {airdrop, createFtMint, createTokenAccount, getTokenBalance} from './utils';
+
+// Accounts below are loaded into the local validator from tests/fixtures, see Anchor.toml.
+// The mint's freeze authority is its master edition, so the Token Metadata program (loaded into the
+// validator) can freeze the renter's account on behalf of the rental PDA.
+const METADATA_PROGRAM_ID = new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+const COLLECTION_MINT = new PublicKey("AoHdJiVrvHbQmUwqckLew17smdv1CxY5LRJaHyyWrt3R");
+const RENTAL_NFT = {
+    mint: new PublicKey("ZZgyCyoEdjcooj452HnYUJJKcTrRLYXS9YcG75mPhMt"),
+    tokenAccount: new PublicKey("D4W4gBjL6pDQfojMK6D5RkWE5PsTyvX7mUiA3nLi2Ty2"),
+    metadata: new PublicKey("9S7Lk6cJWtbGsuxtBRZK88yvFdPf9xQ251VhPw68GjrN"),
+    edition: new PublicKey("5tcM6i3uphoHCeBUT7gdn7uM1j3avnZVgD5ezPUJCFbu"),
+};
+const SECONDS_PER_DAY = 24 * 60 * 60;
+
+describe('rentals', () => {
+    const connection = new Connection("http://localhost:8899", "confirmed");
+    const options = AnchorProvider.defaultOptions();
+    const wallet = NodeWallet.local();
+    const provider = new AnchorProvider(connection, wallet, options);
+
+    anchor.setProvider(provider);
+    const program = anchor.workspace.AnchorAuction as Program<AnchorAuction>;
+
+    const payerAccount = wallet.payer
+    const ownerAccount = Keypair.fromSecretKey(Uint8Array.from(require("./fixtures/seller.json")));
+    const renterAccount = Keypair.generate();
+    const trackId = `rental-${Date.now()}`;
+
+    let ftMintPubkey: PublicKey
+    let ownerFtTokenAccountPubkey: PublicKey
+    let renterFtTokenAccountPubkey: PublicKey
+    let musicTrackPubkey: PublicKey
+    let rentalPubkey: PublicKey
+    let rentalVaultPubkey: PublicKey
+    let renterNftTokenAccountPubkey: PublicKey
+    it("Setup", async () => {
+        await airdrop(connection, [ownerAccount, renterAccount]);
+        ftMintPubkey = await createFtMint(connection, payerAccount);
+        ownerFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, ownerAccount.publicKey);
+        renterFtTokenAccountPubkey = await createTokenAccount(connection, payerAccount, ftMintPubkey, renterAccount.publicKey, 500);
+
+        [rentalPubkey] = await PublicKey.findProgramAddress([Buffer.from("rental"), RENTAL_NFT.mint.toBuffer()], program.programId);
+        [rentalVaultPubkey] = await PublicKey.findProgramAddress([Buffer.from("rental_vault"), RENTAL_NFT.mint.toBuffer()], program.programId);
+        renterNftTokenAccountPubkey = await getAssociatedTokenAddress(RENTAL_NFT.mint, renterAccount.publicKey);
+
+        // a track gated on the NFT's collection, for the renter to play
+        const [musicTrack] = await PublicKey.findProgramAddress([Buffer.from("music_track"), wallet.publicKey.toBuffer(), Buffer.from(trackId)], program.programId);
+        const [authRegistry] = await PublicKey.findProgramAddress([Buffer.from("auth_registry")], program.programId);
+        musicTrackPubkey = musicTrack;
+        await program.rpc.registerMusicTrack(
+            trackId,
+            "ipfs://track",
+            "ipfs://preview",
+            "ipfs://metadata",
+            false,
+            {
+                accounts: {
+                    authority: wallet.publicKey,
+                    musicTrack: musicTrackPubkey,
+                    authRegistry: authRegistry,
+                    systemProgram: SystemProgram.programId,
+                },
+            }
+        );
+        await program.rpc.addAuthorizedCollection(
+            COLLECTION_MINT,
+            {
+                accounts: {
+                    authority: wallet.publicKey,
+                    musicTrack: musicTrackPubkey,
+                },
+            }
+        );
+    });
+
+    const dailyPrice = 10
+    const maxDays = 7
+    it("Escrows the NFT while it's listed for rent", async () => {
+        const signature = await program.rpc.listRental(
+            new anchor.BN(dailyPrice),
+            maxDays,
+            {
+                accounts: {
+                    owner: ownerAccount.publicKey,
+                    nftMint: RENTAL_NFT.mint,
+                    ownerNftAccount: RENTAL_NFT.tokenAccount,
+                    paymentMint: ftMintPubkey,
+                    ownerPaymentAccount: ownerFtTokenAccountPubkey,
+                    rental: rentalPubkey,
+                    rentalVault: rentalVaultPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                signers: [ownerAccount],
+            }
+        );
+        console.log(`list rental tx = ${signature}`)
+
+        assert.equal(await getTokenBalance(RENTAL_NFT.tokenAccount, connection), 0)
+        assert.equal(await getTokenBalance(rentalVaultPubkey, connection), 1)
+    });
+
+    const rentNft = async (days: number) => {
+        return program.rpc.rentNft(
+            days,
+            new anchor.BN(dailyPrice),
+            {
+                accounts: {
+                    renter: renterAccount.publicKey,
+                    nftMint: RENTAL_NFT.mint,
+                    nftEdition: RENTAL_NFT.edition,
+                    rental: rentalPubkey,
+                    rentalVault: rentalVaultPubkey,
+                    renterNftAccount: renterNftTokenAccountPubkey,
+                    royaltyConfig: (await PublicKey.findProgramAddress([Buffer.from("royalty_config"), RENTAL_NFT.mint.toBuffer()], program.programId))[0],
+                    renterPaymentAccount: renterFtTokenAccountPubkey,
+                    ownerPaymentAccount: ownerFtTokenAccountPubkey,
+                    metadataProgram: METADATA_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                },
+                signers: [renterAccount],
+            }
+        );
+    };
+
+    it("Rejects a term longer than the listing allows", async () => {
+        await assert.rejects(
+            rentNft(maxDays + 1),
+            (e: any) => e.error?.errorCode?.code === "InvalidRental" || /InvalidRental/.test(e.toString())
+        );
+    });
+
+    const days = 3
+    it("Delivers the NFT frozen to the renter and pays the owner", async () => {
+        const signature = await rentNft(days);
+        console.log(`rent nft tx = ${signature}`)
+
+        const renterNftTokenAccount = await getAccount(connection, renterNftTokenAccountPubkey);
+        assert.equal(Number(renterNftTokenAccount.amount), 1);
+        assert.ok(renterNftTokenAccount.isFrozen);
+        assert.ok(renterNftTokenAccount.delegate.equals(rentalPubkey));
+        assert.equal(await getTokenBalance(rentalVaultPubkey, connection), 0)
+
+        // no royalty config for this NFT, the owner gets the full rent
+        assert.equal(await getTokenBalance(renterFtTokenAccountPubkey, connection), 500 - dailyPrice * days)
+        assert.equal(await getTokenBalance(ownerFtTokenAccountPubkey, connection), dailyPrice * days)
+
+        const rental = await program.account.rental.fetch(rentalPubkey);
+        assert.ok(rental.renter.equals(renterAccount.publicKey));
+        assert.ok(rental.renterNftAccount.equals(renterNftTokenAccountPubkey));
+        assert.ok(Math.abs(rental.rentedUntil.toNumber() - (Date.now() / 1000 + days * SECONDS_PER_DAY)) < 60);
+    });
+
+    it("Lets the renter play the NFT but not move it", async () => {
+        await program.rpc.verifyPlaybackAuthorization({
+            accounts: {
+                user: renterAccount.publicKey,
+                authority: renterAccount.publicKey,
+                session: null,
+                musicTrack: musicTrackPubkey,
+                userTokenAccount: renterNftTokenAccountPubkey,
+                nftMetadata: RENTAL_NFT.metadata,
+                metadataProgram: METADATA_PROGRAM_ID,
+                accessPass: null,
+                subscription: null,
+                delegation: null,
+                rental: rentalPubkey,
+                userAuth: null,
+                systemProgram: SystemProgram.programId,
+                clock: SYSVAR_CLOCK_PUBKEY,
+            },
+            signers: [renterAccount],
+        });
+
+        await assert.rejects(
+            transfer(connection, payerAccount, renterNftTokenAccountPubkey, RENTAL_NFT.tokenAccount, renterAccount, 1, [], undefined, TOKEN_PROGRAM_ID)
+        );
+        assert.equal(await getTokenBalance(renterNftTokenAccountPubkey, connection), 1)
+    });
+
+    it("Keeps the NFT with the renter until the term is over", async () => {
+        await assert.rejects(
+            program.rpc.endRental({
+                accounts: {
+                    nftMint: RENTAL_NFT.mint,
+                    nftEdition: RENTAL_NFT.edition,
+                    rental: rentalPubkey,
+                    rentalVault: rentalVaultPubkey,
+                    renterNftAccount: renterNftTokenAccountPubkey,
+                    metadataProgram: METADATA_PROGRAM_ID,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+            }),
+            (e: any) => e.error?.errorCode?.code === "RentalNotEnded" || /RentalNotEnded/.test(e.toString())
+        );
+
+        await assert.rejects(
+            program.rpc.delistRental({
+                accounts: {
+                    owner: ownerAccount.publicKey,
+                    nftMint: RENTAL_NFT.mint,
+                    ownerNftAccount: RENTAL_NFT.tokenAccount,
+                    rental: rentalPubkey,
+                    rentalVault: rentalVaultPubkey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                },
+                signers: [ownerAccount],
+            }),
+            (e: any) => e.error?.errorCode?.code === "NftAlreadyRented" || /NftAlreadyRented/.test(e.toString())
+        );
+    });
+});
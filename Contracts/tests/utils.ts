@@ -0,0 +1,77 @@
+import {Connection, Keypair, LAMPORTS_PER_SOL, PublicKey, Signer, SystemProgram} from '@solana/web3.js';
+import {
+    AccountLayout,
+    AuthorityType,
+    createAccount,
+    createInitializeAccountInstruction,
+    createMint,
+    mintTo,
+    setAuthority,
+    TOKEN_PROGRAM_ID
+} from "@solana/spl-token";
+
+// Fixtures shared by the tests, which all run against the local validator
+
+// Fund each account with 2 SOL for fees and rent
+export async function airdrop(connection: Connection, accounts: Keypair[]) {
+    for (const account of accounts) {
+        const signature = await connection.requestAirdrop(account.publicKey, LAMPORTS_PER_SOL * 2);
+        await connection.confirmTransaction(signature);
+    }
+}
+
+// Create a fungible token without decimals, `payer` is its mint authority
+export async function createFtMint(connection: Connection, payer: Signer) {
+    return createMint(connection, payer, payer.publicKey, null, 0, undefined, undefined, TOKEN_PROGRAM_ID);
+}
+
+// Create a token account of `mint` for `owner`, holding `amount` minted by `payer`
+export async function createTokenAccount(connection: Connection, payer: Signer, mint: PublicKey, owner: PublicKey, amount = 0) {
+    const tokenAccount = await createAccount(connection, payer, mint, owner, undefined, undefined, TOKEN_PROGRAM_ID);
+    if (amount > 0) {
+        await mintTo(connection, payer, mint, tokenAccount, payer, amount, [], undefined, TOKEN_PROGRAM_ID);
+    }
+    return tokenAccount;
+}
+
+// Create an NFT held by `owner`, the mint authority is dropped once the single token is minted
+export async function createNft(connection: Connection, payer: Signer, owner: PublicKey) {
+    const mint = await createFtMint(connection, payer);
+    const tokenAccount = await createTokenAccount(connection, payer, mint, owner, 1);
+    await setAuthority(connection, payer, mint, payer, AuthorityType.MintTokens, null);
+    return {mint, tokenAccount};
+}
+
+// Instructions creating the token account `taPubkey` of `mintPubkey`, paid for and owned by `creatorPubkey`
+export async function accountInstructions(connection: Connection, mintPubkey: PublicKey, taPubkey: PublicKey, creatorPubkey: PublicKey) {
+    const createAccount = SystemProgram.createAccount({
+        space: AccountLayout.span,
+        lamports: await connection.getMinimumBalanceForRentExemption(
+            AccountLayout.span
+        ),
+        fromPubkey: creatorPubkey,
+        newAccountPubkey: taPubkey,
+        programId: TOKEN_PROGRAM_ID,
+    });
+    const initAccount = createInitializeAccountInstruction(
+        taPubkey,
+        mintPubkey,
+        creatorPubkey,
+        TOKEN_PROGRAM_ID
+    );
+    return [createAccount, initAccount]
+}
+
+export const getTokenBalance = async (
+    pubkey: PublicKey,
+    connection: Connection
+) => {
+    try {
+        return parseInt(
+            (await connection.getTokenAccountBalance(pubkey)).value.amount
+        );
+    } catch (e) {
+        console.error(`Not a token account ${pubkey}`);
+        return NaN;
+    }
+};